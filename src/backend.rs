@@ -0,0 +1,145 @@
+use std::env;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration::SwayMonitor,
+    inputs::SwayInput,
+    layout::Rect,
+    workspaces::{WorkspaceAction, WorkspaceName, WorkspaceState},
+};
+
+mod hyprland;
+mod niri;
+mod sway;
+
+pub use hyprland::{monitor_rule, HyprlandBackend};
+pub use niri::NiriBackend;
+pub use sway::SwayBackend;
+
+/// compositor that manages the workspaces
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Sway,
+    Hyprland,
+    Niri,
+}
+
+impl BackendKind {
+    /// compositor the daemon runs in, detected from the ipc environment variables
+    ///
+    /// i3 speaks the sway ipc, so xorg sessions are managed by the sway backend as well.
+    pub fn detect() -> Option<Self> {
+        if env::var_os("SWAYSOCK").is_some() || env::var_os("I3SOCK").is_some() {
+            Some(BackendKind::Sway)
+        } else if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Some(BackendKind::Hyprland)
+        } else if env::var_os("NIRI_SOCKET").is_some() {
+            Some(BackendKind::Niri)
+        } else if env::var_os("WAYLAND_DISPLAY").is_none() && env::var_os("DISPLAY").is_some() {
+            Some(BackendKind::Sway)
+        } else {
+            None
+        }
+    }
+}
+
+/// change reported by the compositor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendEvent {
+    /// workspaces were created, moved or focused
+    Workspaces,
+}
+
+/// workspace management of a compositor
+pub trait WorkspaceBackend {
+    fn name(&self) -> &'static str;
+
+    /// all workspaces with the output they are on
+    fn workspaces(&mut self) -> anyhow::Result<Vec<WorkspaceState>>;
+
+    /// move a workspace to an output without changing what is visible on other outputs
+    fn move_workspace(&mut self, workspace: &str, output: &str) -> anyhow::Result<()>;
+
+    fn focus_workspace(&mut self, workspace: &str) -> anyhow::Result<()>;
+
+    /// open a workspace on the first available output of the list when it gets created
+    fn assign_workspace(
+        &mut self,
+        workspace: &WorkspaceName,
+        outputs: &[String],
+    ) -> anyhow::Result<()>;
+
+    /// block and call `on_event` for every change of the workspaces or monitors
+    fn watch(&mut self, on_event: &mut dyn FnMut(BackendEvent)) -> anyhow::Result<()>;
+
+    /// configure the outputs through the compositor instead of the wlr output management protocol
+    ///
+    /// Returns `false` if the compositor has no own way, the outputs are configured over wlr then.
+    fn configure_outputs(&mut self, _monitors: &[SwayMonitor]) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+
+    /// run commands in sway syntax, used for features only sway offers
+    fn run_commands(&mut self, commands: &[String]) -> anyhow::Result<()> {
+        if !commands.is_empty() {
+            anyhow::bail!(
+                "{} does not support sway commands, skipped {} commands",
+                self.name(),
+                commands.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// floating windows with their container id, scratchpad windows included
+    fn floating_windows(&mut self) -> anyhow::Result<Vec<(i64, Rect)>> {
+        Ok(Vec::new())
+    }
+
+    /// input devices that can be configured with sway input commands
+    fn inputs(&mut self) -> anyhow::Result<Vec<SwayInput>> {
+        Ok(Vec::new())
+    }
+}
+
+/// connect to the configured compositor or the one the daemon runs in
+pub fn connect(kind: Option<BackendKind>) -> Option<Box<dyn WorkspaceBackend>> {
+    let backend: anyhow::Result<Box<dyn WorkspaceBackend>> =
+        match kind.or_else(BackendKind::detect) {
+            Some(BackendKind::Sway) => {
+                SwayBackend::connect().map(|backend| Box::new(backend) as Box<dyn WorkspaceBackend>)
+            }
+            Some(BackendKind::Hyprland) => HyprlandBackend::connect()
+                .map(|backend| Box::new(backend) as Box<dyn WorkspaceBackend>),
+            Some(BackendKind::Niri) => {
+                NiriBackend::connect().map(|backend| Box::new(backend) as Box<dyn WorkspaceBackend>)
+            }
+            None => {
+                eprintln!("No supported compositor found, workspaces are left alone");
+                return None;
+            }
+        };
+    match backend {
+        Ok(backend) => Some(backend),
+        Err(err) => {
+            eprintln!("Could not connect to the compositor: {err:#}");
+            None
+        }
+    }
+}
+
+/// run the steps one after another, failing steps are reported and skipped
+pub fn execute(backend: &mut dyn WorkspaceBackend, actions: &[WorkspaceAction]) {
+    for action in actions {
+        let result = match action {
+            WorkspaceAction::Move { workspace, output } => {
+                backend.move_workspace(workspace, output)
+            }
+            WorkspaceAction::Focus(workspace) => backend.focus_workspace(workspace),
+        };
+        if let Err(err) = result {
+            eprintln!("{action:?} failed: {err:#}");
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    env,
+    io::{BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    configuration::SwayMonitor,
+    workspaces::{WorkspaceName, WorkspaceState},
+};
+
+use super::{BackendEvent, WorkspaceBackend};
+
+#[derive(Deserialize)]
+struct HyprlandWorkspace {
+    id: i32,
+    name: String,
+    monitor: String,
+}
+
+#[derive(Deserialize)]
+struct ActiveWorkspace {
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HyprlandMonitor {
+    focused: bool,
+    active_workspace: ActiveWorkspace,
+}
+
+// events of the event socket that change the workspaces or their placement
+const WORKSPACE_EVENTS: [&str; 5] = [
+    "workspace>>",
+    "createworkspace>>",
+    "destroyworkspace>>",
+    "moveworkspace>>",
+    "focusedmon>>",
+];
+
+/// hyprland monitor rule configuring a monitor of the layout, as used by `keyword monitor`
+pub fn monitor_rule(monitor: &SwayMonitor, monitors: &[SwayMonitor]) -> String {
+    if !monitor.enabled {
+        return format!("{},disable", monitor.name);
+    }
+    let mut rule = format!(
+        "{},{}x{}@{},{}x{},{},transform,{}",
+        monitor.name,
+        monitor.width,
+        monitor.height,
+        monitor.fps,
+        monitor.pos_x,
+        monitor.pos_y,
+        monitor.scale,
+        monitor.rotation.transform_id()
+    );
+    // mirrors refer to the output name of the mirrored monitor
+    if let Some(parent) = monitor.mirror.as_ref().and_then(|mirror| {
+        monitors
+            .iter()
+            .find(|other| other.identifier == *mirror)
+            .map(|other| &other.name)
+    }) {
+        rule.push_str(&format!(",mirror,{parent}"));
+    }
+    rule
+}
+
+/// workspaces managed over the hyprland sockets
+pub struct HyprlandBackend {
+    socket_dir: PathBuf,
+}
+
+impl HyprlandBackend {
+    pub fn connect() -> anyhow::Result<Self> {
+        let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")?;
+        // newer versions keep the sockets in the runtime directory
+        let socket_dir = env::var("XDG_RUNTIME_DIR")
+            .map(|run_time_dir| PathBuf::from(run_time_dir).join("hypr").join(&signature))
+            .ok()
+            .filter(|socket_dir| socket_dir.exists())
+            .unwrap_or(PathBuf::from("/tmp/hypr").join(&signature));
+        Ok(Self::new(socket_dir))
+    }
+
+    /// backend for the `.socket.sock` and `.socket2.sock` sockets in the directory
+    pub fn new(socket_dir: PathBuf) -> Self {
+        Self { socket_dir }
+    }
+
+    fn request(&self, request: &str) -> anyhow::Result<String> {
+        let mut socket = UnixStream::connect(self.socket_dir.join(".socket.sock"))?;
+        socket.write_all(request.as_bytes())?;
+        socket.shutdown(Shutdown::Write)?;
+        let mut reply = String::new();
+        socket.read_to_string(&mut reply)?;
+        Ok(reply)
+    }
+
+    fn dispatch(&self, command: &str) -> anyhow::Result<()> {
+        match self.request(command)?.trim() {
+            "ok" => Ok(()),
+            err => anyhow::bail!("{command}: {err}"),
+        }
+    }
+}
+
+impl WorkspaceBackend for HyprlandBackend {
+    fn name(&self) -> &'static str {
+        "hyprland"
+    }
+
+    fn workspaces(&mut self) -> anyhow::Result<Vec<WorkspaceState>> {
+        let workspaces: Vec<HyprlandWorkspace> =
+            serde_json::from_str(&self.request("j/workspaces")?)?;
+        let monitors: Vec<HyprlandMonitor> = serde_json::from_str(&self.request("j/monitors")?)?;
+        Ok(workspaces
+            .into_iter()
+            .map(|workspace| {
+                let monitor = monitors
+                    .iter()
+                    .find(|monitor| monitor.active_workspace.name == workspace.name);
+                WorkspaceState {
+                    // named workspaces have negative ids
+                    num: if workspace.id > 0 { workspace.id } else { -1 },
+                    visible: monitor.is_some(),
+                    focused: monitor.is_some_and(|monitor| monitor.focused),
+                    name: workspace.name,
+                    output: workspace.monitor,
+                }
+            })
+            .collect())
+    }
+
+    fn move_workspace(&mut self, workspace: &str, output: &str) -> anyhow::Result<()> {
+        self.dispatch(&format!(
+            "dispatch moveworkspacetomonitor name:{workspace} {output}"
+        ))
+    }
+
+    fn focus_workspace(&mut self, workspace: &str) -> anyhow::Result<()> {
+        self.dispatch(&format!("dispatch workspace name:{workspace}"))
+    }
+
+    fn assign_workspace(
+        &mut self,
+        workspace: &WorkspaceName,
+        outputs: &[String],
+    ) -> anyhow::Result<()> {
+        // workspace rules of hyprland only take a single monitor
+        match outputs.first() {
+            Some(output) => self.dispatch(&format!(
+                "keyword workspace name:{workspace}, monitor:{output}"
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn watch(&mut self, on_event: &mut dyn FnMut(BackendEvent)) -> anyhow::Result<()> {
+        let events = UnixStream::connect(self.socket_dir.join(".socket2.sock"))?;
+        for line in BufReader::new(events).lines() {
+            let line = line?;
+            if WORKSPACE_EVENTS.iter().any(|event| line.starts_with(event)) {
+                on_event(BackendEvent::Workspaces);
+            }
+        }
+        Ok(())
+    }
+
+    fn configure_outputs(&mut self, monitors: &[SwayMonitor]) -> anyhow::Result<bool> {
+        for monitor in monitors {
+            self.dispatch(&format!(
+                "keyword monitor {}",
+                monitor_rule(monitor, monitors)
+            ))?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HyprlandBackend;
+    use crate::{
+        backend::{BackendEvent, WorkspaceBackend},
+        configuration::{ScreenRotation, SwayMonitor},
+        workspaces::WorkspaceState,
+    };
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixListener,
+        path::PathBuf,
+        sync::mpsc,
+        thread,
+    };
+
+    /// fake hyprland answering the requests on `.socket.sock` with the given replies in order
+    fn fake_hyprland(
+        name: &str,
+        replies: Vec<&'static str>,
+        events: &'static str,
+    ) -> (PathBuf, mpsc::Receiver<String>) {
+        let socket_dir =
+            std::env::temp_dir().join(format!("sway-autodesktop-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&socket_dir);
+        std::fs::create_dir_all(&socket_dir).unwrap();
+        let requests = UnixListener::bind(socket_dir.join(".socket.sock")).unwrap();
+        let event_socket = UnixListener::bind(socket_dir.join(".socket2.sock")).unwrap();
+        let (request_tx, request_rx) = mpsc::channel();
+        thread::spawn(move || {
+            for reply in replies {
+                let (mut stream, _) = requests.accept().unwrap();
+                let mut request = String::new();
+                stream.read_to_string(&mut request).unwrap();
+                let _ = request_tx.send(request);
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        thread::spawn(move || {
+            let (mut stream, _) = event_socket.accept().unwrap();
+            stream.write_all(events.as_bytes()).unwrap();
+        });
+        (socket_dir, request_rx)
+    }
+
+    fn monitor(identifier: &str, name: &str, mirror: Option<&str>) -> SwayMonitor {
+        SwayMonitor {
+            identifier: identifier.to_string(),
+            mirror: mirror.map(|mirror| mirror.to_string()),
+            enabled: true,
+            name: name.to_string(),
+            width: 2560,
+            height: 1440,
+            fps: 59.951,
+            pos_x: 1920,
+            pos_y: 0,
+            scale: 1.25,
+            rotation: ScreenRotation::Portrait,
+            workspaces: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hyprland_workspaces_and_outputs() {
+        let (socket_dir, requests) = fake_hyprland(
+            "workspaces",
+            vec![
+                r#"[{"id": 1, "name": "1", "monitor": "DP-1"}, {"id": -98, "name": "mail", "monitor": "eDP-1"}]"#,
+                r#"[{"name": "DP-1", "focused": true, "activeWorkspace": {"id": 1, "name": "1"}}]"#,
+                "ok",
+                "ok",
+                "ok",
+            ],
+            "activewindow>>kitty,fish\nmonitoradded>>DP-2\nworkspace>>2\n",
+        );
+        let mut backend = HyprlandBackend::new(socket_dir.clone());
+        assert_eq!(
+            backend.workspaces().unwrap(),
+            vec![
+                WorkspaceState {
+                    name: "1".to_string(),
+                    num: 1,
+                    output: "DP-1".to_string(),
+                    visible: true,
+                    focused: true,
+                },
+                WorkspaceState {
+                    name: "mail".to_string(),
+                    num: -1,
+                    output: "eDP-1".to_string(),
+                    visible: false,
+                    focused: false,
+                },
+            ]
+        );
+        backend.move_workspace("mail", "DP-1").unwrap();
+        let mut disabled = monitor("eDP-1", "eDP-1", None);
+        disabled.enabled = false;
+        assert!(backend
+            .configure_outputs(&[monitor("Dell XXXXXXA", "DP-1", Some("eDP-1")), disabled])
+            .unwrap());
+        assert_eq!(
+            requests.iter().skip(2).collect::<Vec<String>>(),
+            [
+                "dispatch moveworkspacetomonitor name:mail DP-1",
+                "keyword monitor DP-1,2560x1440@59.951,1920x0,1.25,transform,1,mirror,eDP-1",
+                "keyword monitor eDP-1,disable",
+            ]
+        );
+
+        let mut events = Vec::new();
+        backend.watch(&mut |event| events.push(event)).unwrap();
+        // monitor hotplugs are followed by the output state thread
+        assert_eq!(events, [BackendEvent::Workspaces]);
+        let _ = std::fs::remove_dir_all(socket_dir);
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use wayland_client::protocol::wl_output::Transform;
+
+use crate::{
+    configuration::SwayMonitor,
+    wlr_output_state::{
+        MonitorInformation, MonitorInformationBuilder, MonitorModeBuilder, OutputId,
+    },
+    workspaces::{WorkspaceName, WorkspaceState},
+};
+
+use super::{BackendEvent, WorkspaceBackend};
+
+#[derive(Deserialize)]
+struct NiriWorkspace {
+    id: u64,
+    idx: i32,
+    name: Option<String>,
+    output: Option<String>,
+    is_active: bool,
+    is_focused: bool,
+}
+
+#[derive(Deserialize)]
+struct NiriMode {
+    width: i32,
+    height: i32,
+    refresh_rate: i32,
+    is_preferred: bool,
+}
+
+#[derive(Deserialize)]
+struct NiriLogicalOutput {
+    x: i32,
+    y: i32,
+    scale: f64,
+    transform: String,
+}
+
+#[derive(Deserialize)]
+struct NiriOutput {
+    name: String,
+    make: String,
+    model: String,
+    serial: Option<String>,
+    physical_size: Option<(i32, i32)>,
+    modes: Vec<NiriMode>,
+    current_mode: Option<usize>,
+    /// only set for enabled outputs
+    logical: Option<NiriLogicalOutput>,
+}
+
+// events of the event stream that change workspaces
+const WORKSPACE_EVENTS: [&str; 2] = ["WorkspacesChanged", "WorkspaceActivated"];
+
+fn transform(transform: &str) -> Transform {
+    match transform {
+        "90" => Transform::_90,
+        "180" => Transform::_180,
+        "270" => Transform::_270,
+        "Flipped" => Transform::Flipped,
+        "flipped-90" => Transform::Flipped90,
+        "flipped-180" => Transform::Flipped180,
+        "flipped-270" => Transform::Flipped270,
+        _ => Transform::Normal,
+    }
+}
+
+fn monitor_information(output: &NiriOutput) -> anyhow::Result<MonitorInformation> {
+    let mut builder = MonitorInformationBuilder::default();
+    builder
+        .name(output.name.clone())
+        .make(output.make.clone())
+        .model(output.model.clone())
+        .description(format!(
+            "{} {} ({})",
+            output.make, output.model, output.name
+        ))
+        .size(output.physical_size.unwrap_or_default())
+        .enabled(output.logical.is_some() as i32)
+        .serial(output.serial.clone());
+    match &output.logical {
+        Some(logical) => builder
+            .position((logical.x, logical.y))
+            .scale(logical.scale)
+            .transform(transform(&logical.transform)),
+        None => builder.scale(1.).transform(Transform::Normal),
+    };
+    for (index, mode) in output.modes.iter().enumerate() {
+        builder.add_mode(
+            MonitorModeBuilder::default()
+                .size((mode.width, mode.height))
+                .refresh(mode.refresh_rate)
+                .preferred(mode.is_preferred)
+                .current(output.current_mode == Some(index))
+                .build()?,
+        );
+    }
+    Ok(builder.build()?)
+}
+
+/// niri output actions configuring a monitor of the layout
+fn output_actions(monitor: &SwayMonitor) -> Vec<Value> {
+    if !monitor.enabled {
+        return vec![json!("Off")];
+    }
+    let transform = match monitor.rotation.degrees() {
+        0 => "Normal".to_string(),
+        degrees => degrees.to_string(),
+    };
+    vec![
+        json!("On"),
+        json!({"Mode": {"mode": {"Specific": {
+            "width": monitor.width,
+            "height": monitor.height,
+            "refresh": monitor.fps,
+        }}}}),
+        json!({"Scale": {"scale": {"Specific": monitor.scale}}}),
+        json!({"Transform": {"transform": transform}}),
+        json!({"Position": {"position": {"Specific": {"x": monitor.pos_x, "y": monitor.pos_y}}}}),
+    ]
+}
+
+/// outputs and workspaces managed over the niri ipc socket
+pub struct NiriBackend {
+    socket: PathBuf,
+}
+
+impl NiriBackend {
+    pub fn connect() -> anyhow::Result<Self> {
+        Ok(Self::new(PathBuf::from(env::var("NIRI_SOCKET")?)))
+    }
+
+    pub fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+
+    /// send a request and return the reply, the connection is kept for following events
+    fn request(&self, request: Value) -> anyhow::Result<(Value, BufReader<UnixStream>)> {
+        let mut socket = UnixStream::connect(&self.socket)?;
+        socket.write_all(format!("{request}\n").as_bytes())?;
+        let mut reply = BufReader::new(socket);
+        let mut line = String::new();
+        reply.read_line(&mut line)?;
+        match serde_json::from_str::<Value>(&line)? {
+            Value::Object(mut result) if result.contains_key("Ok") => {
+                Ok((result.remove("Ok").unwrap_or_default(), reply))
+            }
+            Value::Object(mut result) if result.contains_key("Err") => {
+                anyhow::bail!("{request}: {}", result.remove("Err").unwrap_or_default())
+            }
+            other => anyhow::bail!("{request}: unexpected reply {other}"),
+        }
+    }
+
+    fn action(&self, action: Value) -> anyhow::Result<()> {
+        self.request(json!({ "Action": action }))?;
+        Ok(())
+    }
+
+    fn niri_workspaces(&self) -> anyhow::Result<Vec<NiriWorkspace>> {
+        let (reply, _) = self.request(json!("Workspaces"))?;
+        Ok(serde_json::from_value(reply["Workspaces"].clone())?)
+    }
+
+    /// workspace reference of an action, unnamed workspaces are referred to by their id
+    fn reference(&self, workspace: &str) -> anyhow::Result<Value> {
+        let workspaces = self.niri_workspaces()?;
+        if workspaces
+            .iter()
+            .any(|niri_workspace| niri_workspace.name.as_deref() == Some(workspace))
+        {
+            return Ok(json!({"Name": workspace}));
+        }
+        // unnamed workspaces go by their index, an index reference would only look at the focused
+        // output though
+        Ok(workspaces
+            .iter()
+            .find(|niri_workspace| {
+                niri_workspace.name.is_none() && niri_workspace.idx.to_string() == workspace
+            })
+            .map_or_else(
+                || json!({"Name": workspace}),
+                |niri_workspace| json!({"Id": niri_workspace.id}),
+            ))
+    }
+
+    /// connected outputs keyed by their name
+    pub fn monitors(&self) -> anyhow::Result<HashMap<OutputId, MonitorInformation>> {
+        let (reply, _) = self.request(json!("Outputs"))?;
+        let outputs: HashMap<String, NiriOutput> =
+            serde_json::from_value(reply["Outputs"].clone())?;
+        let mut monitors = HashMap::new();
+        for output in outputs.values() {
+            monitors.insert(
+                OutputId::Name(output.name.clone()),
+                monitor_information(output)?,
+            );
+        }
+        Ok(monitors)
+    }
+}
+
+impl WorkspaceBackend for NiriBackend {
+    fn name(&self) -> &'static str {
+        "niri"
+    }
+
+    fn workspaces(&mut self) -> anyhow::Result<Vec<WorkspaceState>> {
+        Ok(self
+            .niri_workspaces()?
+            .into_iter()
+            .map(|workspace| WorkspaceState {
+                // unnamed workspaces are only known by their index on the output
+                num: if workspace.name.is_none() {
+                    workspace.idx
+                } else {
+                    -1
+                },
+                name: workspace.name.unwrap_or(workspace.idx.to_string()),
+                output: workspace.output.unwrap_or_default(),
+                visible: workspace.is_active,
+                focused: workspace.is_focused,
+            })
+            .collect())
+    }
+
+    fn move_workspace(&mut self, workspace: &str, output: &str) -> anyhow::Result<()> {
+        self.action(json!({"MoveWorkspaceToMonitor": {
+            "output": output,
+            "reference": self.reference(workspace)?,
+        }}))
+    }
+
+    fn focus_workspace(&mut self, workspace: &str) -> anyhow::Result<()> {
+        self.action(json!({"FocusWorkspace": {"reference": self.reference(workspace)?}}))
+    }
+
+    fn assign_workspace(
+        &mut self,
+        _workspace: &WorkspaceName,
+        _outputs: &[String],
+    ) -> anyhow::Result<()> {
+        // niri only takes `open-on-output` rules from its configuration file
+        Ok(())
+    }
+
+    fn watch(&mut self, on_event: &mut dyn FnMut(BackendEvent)) -> anyhow::Result<()> {
+        // the event stream does not report outputs, they are followed over wlr or polled
+        let (_, events) = self.request(json!("EventStream"))?;
+        for line in events.lines() {
+            let event: Value = serde_json::from_str(&line?)?;
+            if WORKSPACE_EVENTS
+                .iter()
+                .any(|workspace_event| event.get(workspace_event).is_some())
+            {
+                on_event(BackendEvent::Workspaces);
+            }
+        }
+        Ok(())
+    }
+
+    fn configure_outputs(&mut self, monitors: &[SwayMonitor]) -> anyhow::Result<bool> {
+        for monitor in monitors {
+            for action in output_actions(monitor) {
+                let (reply, _) =
+                    self.request(json!({"Output": {"output": monitor.name, "action": action}}))?;
+                if reply["OutputConfigChanged"] == "OutputWasMissing" {
+                    anyhow::bail!("niri does not know the output {}", monitor.name);
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NiriBackend;
+    use crate::{
+        backend::{BackendEvent, WorkspaceBackend},
+        configuration::{ScreenRotation, SwayMonitor},
+        wlr_output_state::OutputId,
+        workspaces::WorkspaceState,
+    };
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixListener,
+        sync::mpsc,
+        thread,
+    };
+
+    #[test]
+    fn niri_workspaces_and_outputs() {
+        let socket_dir =
+            std::env::temp_dir().join(format!("sway-autodesktop-niri-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&socket_dir);
+        std::fs::create_dir_all(&socket_dir).unwrap();
+        let socket = socket_dir.join("niri.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let workspaces = r#"{"Ok":{"Workspaces":[{"id":4,"idx":1,"name":"web","output":"DP-1","is_active":true,"is_focused":false},{"id":5,"idx":2,"name":null,"output":"eDP-1","is_active":false,"is_focused":false}]}}"#;
+        let replies = [
+            workspaces,
+            r#"{"Ok":{"Outputs":{"DP-1":{"name":"DP-1","make":"Dell Inc.","model":"DELL U2720Q","serial":"XXXXXXA","physical_size":[600,340],"modes":[{"width":3840,"height":2160,"refresh_rate":59997,"is_preferred":true},{"width":2560,"height":1440,"refresh_rate":59951,"is_preferred":false}],"current_mode":1,"vrr_supported":false,"vrr_enabled":false,"logical":{"x":1920,"y":0,"width":2560,"height":1440,"scale":1.0,"transform":"Normal"}}}}}"#,
+            workspaces,
+            r#"{"Ok":"Handled"}"#,
+            workspaces,
+            r#"{"Ok":"Handled"}"#,
+            r#"{"Ok":{"OutputConfigChanged":"Applied"}}"#,
+            r#"{"Ok":{"OutputConfigChanged":"OutputWasMissing"}}"#,
+            workspaces,
+            r#"{"Err":"unknown workspace"}"#,
+            "{\"Ok\":\"Handled\"}\n{\"WindowFocusChanged\":{\"id\":null}}\n{\"WorkspaceActivated\":{\"id\":5,\"focused\":true}}",
+        ];
+        let (request_tx, requests) = mpsc::channel();
+        thread::spawn(move || {
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let _ = request_tx.send(request.trim().to_string());
+                stream.write_all(format!("{reply}\n").as_bytes()).unwrap();
+            }
+        });
+
+        let mut backend = NiriBackend::new(socket);
+        assert_eq!(
+            backend.workspaces().unwrap(),
+            vec![
+                WorkspaceState {
+                    name: "web".to_string(),
+                    num: -1,
+                    output: "DP-1".to_string(),
+                    visible: true,
+                    focused: false,
+                },
+                WorkspaceState {
+                    name: "2".to_string(),
+                    num: 2,
+                    output: "eDP-1".to_string(),
+                    visible: false,
+                    focused: false,
+                },
+            ]
+        );
+        let monitors = backend.monitors().unwrap();
+        let monitor_info = &monitors[&OutputId::Name("DP-1".to_string())];
+        assert_eq!(monitor_info.identifier(), "Dell Inc. XXXXXXA");
+        assert_eq!(*monitor_info.preffered_mode().size(), (3840, 2160));
+        assert_eq!(*monitor_info.active_mode().size(), (2560, 1440));
+
+        backend.move_workspace("web", "eDP-1").unwrap();
+        backend.move_workspace("2", "DP-1").unwrap();
+        let monitor = SwayMonitor {
+            identifier: "Dell Inc. XXXXXXA".to_string(),
+            mirror: None,
+            enabled: false,
+            name: "DP-1".to_string(),
+            width: 2560,
+            height: 1440,
+            fps: 59.951,
+            pos_x: 0,
+            pos_y: 0,
+            scale: 1.,
+            rotation: ScreenRotation::Landscape,
+            workspaces: Vec::new(),
+        };
+        assert!(backend
+            .configure_outputs(std::slice::from_ref(&monitor))
+            .unwrap());
+        assert!(backend.configure_outputs(&[monitor]).is_err());
+        assert!(backend.focus_workspace("mail").is_err());
+        assert_eq!(
+            requests
+                .iter()
+                .skip(2)
+                .take(8)
+                .filter(|request| request != "\"Workspaces\"")
+                .collect::<Vec<String>>(),
+            [
+                r#"{"Action":{"MoveWorkspaceToMonitor":{"output":"eDP-1","reference":{"Name":"web"}}}}"#,
+                r#"{"Action":{"MoveWorkspaceToMonitor":{"output":"DP-1","reference":{"Id":5}}}}"#,
+                r#"{"Output":{"action":"Off","output":"DP-1"}}"#,
+                r#"{"Output":{"action":"Off","output":"DP-1"}}"#,
+                r#"{"Action":{"FocusWorkspace":{"reference":{"Name":"mail"}}}}"#,
+            ]
+        );
+
+        let mut events = Vec::new();
+        backend.watch(&mut |event| events.push(event)).unwrap();
+        assert_eq!(events, [BackendEvent::Workspaces]);
+        let _ = std::fs::remove_dir_all(socket_dir);
+    }
+}
@@ -0,0 +1,215 @@
+use swayipc::{Connection, EventType, Node, SendEvents};
+
+use crate::{
+    inputs::{InputEvents, InputSettings, SwayInput},
+    layout::Rect,
+    workspaces::{quote, WorkspaceName, WorkspaceState},
+};
+
+use super::{BackendEvent, WorkspaceBackend};
+
+/// workspaces managed over the sway ipc socket
+pub struct SwayBackend {
+    connection: Connection,
+}
+
+impl SwayBackend {
+    pub fn connect() -> anyhow::Result<Self> {
+        Ok(Self {
+            connection: Connection::new()?,
+        })
+    }
+
+    /// run a command and fail if sway reports an error for it
+    fn run(&mut self, command: String) -> anyhow::Result<()> {
+        for outcome in self.connection.run_command(command)? {
+            outcome?;
+        }
+        Ok(())
+    }
+}
+
+impl WorkspaceBackend for SwayBackend {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn workspaces(&mut self) -> anyhow::Result<Vec<WorkspaceState>> {
+        Ok(self
+            .connection
+            .get_workspaces()?
+            .into_iter()
+            .map(|workspace| WorkspaceState {
+                name: workspace.name,
+                num: workspace.num,
+                output: workspace.output,
+                visible: workspace.visible,
+                focused: workspace.focused,
+            })
+            .collect())
+    }
+
+    fn move_workspace(&mut self, workspace: &str, output: &str) -> anyhow::Result<()> {
+        // criteria only match windows, empty or missing workspaces have to be focused to move them
+        if self.run(move_command(workspace, output)).is_ok() {
+            return Ok(());
+        }
+        self.focus_workspace(workspace)?;
+        self.run(format!("move workspace to output {}", quote(output)))
+    }
+
+    fn focus_workspace(&mut self, workspace: &str) -> anyhow::Result<()> {
+        self.run(format!(
+            "workspace --no-auto-back-and-forth {}",
+            quote(workspace)
+        ))
+    }
+
+    fn assign_workspace(
+        &mut self,
+        workspace: &WorkspaceName,
+        outputs: &[String],
+    ) -> anyhow::Result<()> {
+        self.run(assign_command(workspace, outputs))
+    }
+
+    fn watch(&mut self, on_event: &mut dyn FnMut(BackendEvent)) -> anyhow::Result<()> {
+        // output changes reach the daemon over wlr output management
+        for event in Connection::new()?.subscribe([EventType::Workspace])? {
+            event?;
+            on_event(BackendEvent::Workspaces);
+        }
+        Ok(())
+    }
+
+    fn run_commands(&mut self, commands: &[String]) -> anyhow::Result<()> {
+        // the commands do not depend on each other, a failing one does not stop the rest
+        let failures = commands
+            .iter()
+            .filter_map(|command| {
+                let err = self.run(command.clone()).err()?;
+                Some(format!("{command}: {err:#}"))
+            })
+            .collect::<Vec<String>>();
+        if !failures.is_empty() {
+            anyhow::bail!("sway commands failed: {}", failures.join(", "));
+        }
+        Ok(())
+    }
+
+    fn floating_windows(&mut self) -> anyhow::Result<Vec<(i64, Rect)>> {
+        Ok(floating_windows(&self.connection.get_tree()?))
+    }
+
+    fn inputs(&mut self) -> anyhow::Result<Vec<SwayInput>> {
+        Ok(self
+            .connection
+            .get_inputs()?
+            .into_iter()
+            .map(|input| {
+                let libinput = input.libinput.as_ref();
+                SwayInput {
+                    identifier: input.identifier,
+                    input_type: input.input_type,
+                    current: InputSettings {
+                        events: libinput
+                            .and_then(|libinput| libinput.send_events)
+                            .and_then(input_events),
+                        xkb_layout: None,
+                        accel: libinput.and_then(|libinput| libinput.accel_speed),
+                        map_to_output: None,
+                    },
+                }
+            })
+            .collect())
+    }
+}
+
+fn input_events(send_events: SendEvents) -> Option<InputEvents> {
+    match send_events {
+        SendEvents::Enabled => Some(InputEvents::Enabled),
+        SendEvents::Disabled => Some(InputEvents::Disabled),
+        SendEvents::DisabledOnExternalMouse => Some(InputEvents::DisabledOnExternalMouse),
+        _ => None,
+    }
+}
+
+/// floating windows anywhere in the sway tree, windows in the scratchpad included
+fn floating_windows(node: &Node) -> Vec<(i64, Rect)> {
+    let mut windows = node
+        .floating_nodes
+        .iter()
+        .map(|floating| {
+            let rect = floating.rect;
+            (
+                floating.id,
+                Rect::new(rect.x, rect.y, rect.width, rect.height),
+            )
+        })
+        .collect::<Vec<(i64, Rect)>>();
+    for child in &node.nodes {
+        windows.extend(floating_windows(child));
+    }
+    windows
+}
+
+/// sway command installing a rule to open the workspace on the first available output of the list
+fn assign_command(workspace: &WorkspaceName, outputs: &[String]) -> String {
+    format!(
+        "workspace {} output {}",
+        quote(workspace.as_str()),
+        outputs
+            .iter()
+            .map(|output| quote(output))
+            .collect::<Vec<String>>()
+            .join(" ")
+    )
+}
+
+/// sway command moving the workspace of the matched windows to an output without focusing it
+fn move_command(workspace: &str, output: &str) -> String {
+    // the workspace criteria is a regular expression
+    let pattern = workspace
+        .chars()
+        .map(|c| match c {
+            '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => {
+                format!("\\{c}")
+            }
+            c => c.to_string(),
+        })
+        .collect::<String>();
+    format!(
+        "[workspace={}] move workspace to output {}",
+        quote(&format!("^{pattern}$")),
+        quote(output)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{assign_command, move_command};
+    use crate::workspaces::WorkspaceName;
+
+    #[test]
+    fn assign_workspace_to_outputs() {
+        assert_eq!(
+            assign_command(
+                &WorkspaceName::new("3:web"),
+                &["DP-1".to_string(), "Dell \"XXXXXXA\"".to_string()]
+            ),
+            "workspace \"3:web\" output \"DP-1\" \"Dell \\\"XXXXXXA\\\"\""
+        );
+    }
+
+    #[test]
+    fn move_workspace_without_focus() {
+        assert_eq!(
+            move_command("1", "DP-1"),
+            "[workspace=\"^1$\"] move workspace to output \"DP-1\""
+        );
+        assert_eq!(
+            move_command("3:web (2)", "DP-1"),
+            "[workspace=\"^3:web \\\\(2\\\\)$\"] move workspace to output \"DP-1\""
+        );
+    }
+}
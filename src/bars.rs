@@ -0,0 +1,78 @@
+use std::{fs, io, path::PathBuf, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::workspaces::quote;
+
+/// output list file for waybar, included from the waybar configuration
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WaybarOutputs {
+    pub file: PathBuf,
+    /// screen identifiers or output names that show waybar
+    pub outputs: Vec<String>,
+    /// signal that makes waybar reload its configuration
+    #[serde(default = "WaybarOutputs::default_signal")]
+    pub signal: String,
+}
+
+impl WaybarOutputs {
+    fn default_signal() -> String {
+        "SIGUSR2".to_string()
+    }
+
+    /// write the output list and tell waybar to reload it
+    pub fn update(&self, outputs: &[String]) -> io::Result<()> {
+        fs::write(&self.file, output_list(outputs))?;
+        Command::new("pkill")
+            .args(["--signal", &self.signal, "-x", "waybar"])
+            .output()?;
+        Ok(())
+    }
+}
+
+/// sway commands limiting a bar to the given outputs
+pub fn bar_commands(bar_id: &str, outputs: &[String]) -> Vec<String> {
+    // `*` clears the outputs of the bar, every following output is added to the list
+    let mut commands = vec![format!("bar {} output *", quote(bar_id))];
+    for output in outputs {
+        commands.push(format!("bar {} output {}", quote(bar_id), quote(output)));
+    }
+    commands
+}
+
+/// waybar configuration snippet containing only the output list
+pub fn output_list(outputs: &[String]) -> String {
+    format!(
+        "{{ \"output\": [{}] }}\n",
+        outputs
+            .iter()
+            .map(|output| quote(output))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bar_commands, output_list, WaybarOutputs};
+
+    #[test]
+    fn bar_outputs() {
+        let outputs = vec!["DP-1".to_string(), "eDP-1".to_string()];
+        assert_eq!(
+            bar_commands("bar-0", &outputs),
+            [
+                "bar \"bar-0\" output *",
+                "bar \"bar-0\" output \"DP-1\"",
+                "bar \"bar-0\" output \"eDP-1\"",
+            ]
+        );
+        assert_eq!(
+            output_list(&outputs),
+            "{ \"output\": [\"DP-1\", \"eDP-1\"] }\n"
+        );
+        let waybar: WaybarOutputs =
+            serde_yaml::from_str("{ file: /tmp/outputs.json, outputs: [eDP-1] }").unwrap();
+        assert_eq!(waybar.signal, "SIGUSR2");
+    }
+}
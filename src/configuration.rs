@@ -1,19 +1,37 @@
 use derive_getters::Getters;
-use id_tree::{Node, NodeId, Tree, TreeBuilder};
 use itertools::Itertools;
 use libmonitor::mccs::features::InputSource;
 use libmonitor::{ddc::DdcDevice, Monitor};
-use serde::{Deserialize, Serialize};
-use std::sync::mpsc::Sender;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
 use std::{
     collections::{BTreeMap, HashMap},
+    fmt::Display,
     path::PathBuf,
     process::Command,
+    time::Duration,
 };
-use wayland_client::backend::ObjectId;
 use wayland_client::protocol::wl_output::Transform;
 
-use crate::{ddc::MonitorInputSourceMatcher, wlr_output_state::MonitorInformation};
+use crate::{
+    backend::{self, BackendKind},
+    bars::{self, WaybarOutputs},
+    ddc::MonitorInputSourceMatcher,
+    inputs::{self, InputSettings},
+    layout::{
+        self, LayoutCheck, LayoutError, LayoutViolation, PlacedScreen, PositionConstraint, Rect,
+        SolverScreen,
+    },
+    snippets::OutputMode,
+    windows::{self, FloatingWindows, WindowRule, WindowTarget},
+    wlr_output_state::{HeadConfiguration, MonitorInformation, OutputId},
+    workspaces::{
+        self, MissingWorkspaces, WorkspaceAssignment, WorkspaceDistribution, WorkspaceName,
+    },
+};
+
+// milliseconds to wait for the compositor to answer an output configuration
+pub const APPLY_TIMEOUT: u64 = 5000;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ScreenRotation {
@@ -34,6 +52,17 @@ impl Into<Transform> for ScreenRotation {
     }
 }
 
+impl From<Transform> for ScreenRotation {
+    fn from(transform: Transform) -> Self {
+        match transform {
+            Transform::_90 | Transform::Flipped90 => ScreenRotation::Portrait,
+            Transform::_180 | Transform::Flipped180 => ScreenRotation::LandscapeReversed,
+            Transform::_270 | Transform::Flipped270 => ScreenRotation::PortraitReversed,
+            _ => ScreenRotation::Landscape,
+        }
+    }
+}
+
 impl ScreenRotation {
     pub fn transform_size(&self, size: (i32, i32)) -> (i32, i32) {
         match self {
@@ -42,6 +71,11 @@ impl ScreenRotation {
         }
     }
 
+    /// clockwise rotation in degrees
+    pub fn degrees(&self) -> u16 {
+        self.transform_id() as u16 * 90
+    }
+
     pub fn transform_id(&self) -> u8 {
         match self {
             ScreenRotation::Landscape => 0,
@@ -52,7 +86,7 @@ impl ScreenRotation {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum ScreenPositionRelative {
     Root,
     Over(String),
@@ -66,35 +100,101 @@ pub enum ScreenPositionRelative {
     Mirror(String),
 }
 
-impl ScreenPositionRelative {
-    pub fn parent(&self) -> Option<&str> {
-        match self {
-            ScreenPositionRelative::Root => None,
-            ScreenPositionRelative::Over(identifer)
-            | ScreenPositionRelative::Under(identifer)
-            | ScreenPositionRelative::Left(identifer)
-            | ScreenPositionRelative::Right(identifer)
-            | ScreenPositionRelative::LeftOver(identifer)
-            | ScreenPositionRelative::LeftUnder(identifer)
-            | ScreenPositionRelative::RightOver(identifer)
-            | ScreenPositionRelative::RightUnder(identifer)
-            | ScreenPositionRelative::Mirror(identifer) => Some(&identifer),
-        }
+/// subpixel layout of the screen used for font rendering
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Subpixel {
+    Rgb,
+    Bgr,
+    Vrgb,
+    Vbgr,
+    None,
+}
+
+/// sway output settings that are not part of the wlr output management protocol
+///
+/// Values that are not set are left untouched.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SwayOutputOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    subpixel: Option<Subpixel>,
+    #[serde(
+        default,
+        deserialize_with = "render_bit_depth",
+        skip_serializing_if = "Option::is_none"
+    )]
+    render_bit_depth: Option<u8>,
+    /// milliseconds, 0 turns it off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_render_time: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allow_tearing: Option<bool>,
+    /// `srgb` or the path of an icc profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    color_profile: Option<String>,
+    /// solid colour like `#1d2021`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    background: Option<String>,
+}
+
+/// sway only renders with 6, 8 or 10 bits per channel
+fn render_bit_depth<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+    match Option::<u8>::deserialize(deserializer)? {
+        Some(bit_depth) if ![6, 8, 10].contains(&bit_depth) => Err(Error::custom(format!(
+            "render_bit_depth has to be 6, 8 or 10, got {bit_depth}"
+        ))),
+        bit_depth => Ok(bit_depth),
     }
+}
 
-    pub fn offset(&self, parent_size: (i32, i32), own_size: (i32, i32)) -> (i32, i32) {
-        match self {
-            ScreenPositionRelative::Root => (0, 0),
-            ScreenPositionRelative::Over(_) => (0, -1 * own_size.1),
-            ScreenPositionRelative::Under(_) => (0, parent_size.1),
-            ScreenPositionRelative::Left(_) => (-1 * own_size.0, 0),
-            ScreenPositionRelative::Right(_) => (parent_size.0, 0),
-            ScreenPositionRelative::LeftOver(_) => (-1 * own_size.0, -1 * own_size.1),
-            ScreenPositionRelative::LeftUnder(_) => (-1 * own_size.0, parent_size.1),
-            ScreenPositionRelative::RightOver(_) => (parent_size.0, -1 * own_size.1),
-            ScreenPositionRelative::RightUnder(_) => (parent_size.0, parent_size.1),
-            ScreenPositionRelative::Mirror(_) => (0, 0),
+impl SwayOutputOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == SwayOutputOptions::default()
+    }
+
+    /// sway commands setting the options on an output
+    pub fn commands(&self, output: &str) -> Vec<String> {
+        let output = workspaces::quote(output);
+        let mut options = Vec::new();
+        if let Some(subpixel) = self.subpixel {
+            let subpixel = match subpixel {
+                Subpixel::Rgb => "rgb",
+                Subpixel::Bgr => "bgr",
+                Subpixel::Vrgb => "vrgb",
+                Subpixel::Vbgr => "vbgr",
+                Subpixel::None => "none",
+            };
+            options.push(format!("subpixel {subpixel}"));
+        }
+        if let Some(bit_depth) = self.render_bit_depth {
+            options.push(format!("render_bit_depth {bit_depth}"));
+        }
+        match self.max_render_time {
+            Some(0) => options.push("max_render_time off".to_string()),
+            Some(milliseconds) => options.push(format!("max_render_time {milliseconds}")),
+            None => {}
+        }
+        if let Some(allow_tearing) = self.allow_tearing {
+            options.push(format!(
+                "allow_tearing {}",
+                if allow_tearing { "yes" } else { "no" }
+            ));
+        }
+        match self.color_profile.as_deref() {
+            Some("srgb") => options.push("color_profile srgb".to_string()),
+            Some(icc) => options.push(format!("color_profile icc {}", workspaces::quote(icc))),
+            None => {}
+        }
+        if let Some(background) = &self.background {
+            options.push(format!(
+                "background {} solid_color",
+                workspaces::quote(background)
+            ));
         }
+        options
+            .into_iter()
+            .map(|option| format!("output {output} {option}"))
+            .collect()
     }
 }
 
@@ -105,16 +205,38 @@ pub struct ScreenConfiguration {
     rotation: ScreenRotation,
     #[serde(default)]
     display_output_code: MonitorInputSourceMatcher,
+    #[serde(default)]
     wallpaper: PathBuf,
-    position: ScreenPositionRelative,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    position: Option<ScreenPositionRelative>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    constraints: Vec<PositionConstraint>,
     #[serde(default)]
-    workspaces: Vec<u8>,
+    workspaces: Vec<WorkspaceAssignment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mapped_inputs: Vec<String>,
+    #[serde(default, skip_serializing_if = "SwayOutputOptions::is_empty")]
+    sway_output: SwayOutputOptions,
     enabled: bool,
 }
 
-#[derive(Debug)]
+impl ScreenConfiguration {
+    /// all constraints for the position of the screen, including the ones from its relative position
+    pub fn position_constraints(&self) -> Vec<PositionConstraint> {
+        let mut constraints = self
+            .position
+            .as_ref()
+            .map(|position| position.constraints())
+            .unwrap_or_default();
+        constraints.extend(self.constraints.iter().cloned());
+        constraints
+    }
+}
+
+#[derive(Debug, Clone)]
 // collect settings required to configure hyprland
 pub struct SwayMonitor {
+    pub identifier: String,
     pub mirror: Option<String>,
     pub enabled: bool,
     pub name: String,
@@ -125,7 +247,191 @@ pub struct SwayMonitor {
     pub pos_y: i32,
     pub scale: f64,
     pub rotation: ScreenRotation,
-    pub workspaces: Vec<u8>,
+    pub workspaces: Vec<WorkspaceName>,
+}
+
+impl SwayMonitor {
+    /// area the monitor takes up in the computed layout, disabled monitors do not take up any space
+    pub fn rect(&self) -> Rect {
+        let (width, height) = if self.enabled {
+            self.rotation.transform_size((self.width, self.height))
+        } else {
+            (0, 0)
+        };
+        Rect::new(self.pos_x, self.pos_y, width, height)
+    }
+
+    /// area the monitor will occupy in the compositor layout (in logical pixels)
+    pub fn logical_rect(&self) -> Rect {
+        Rect::logical(
+            (self.pos_x, self.pos_y),
+            self.rotation.transform_size((self.width, self.height)),
+            self.scale,
+        )
+    }
+}
+
+impl Display for SwayMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): ", self.identifier, self.name)?;
+        if !self.enabled {
+            return write!(f, "disabled");
+        }
+        write!(
+            f,
+            "{}x{}@{:.2}Hz at {},{} scale {} transform {:?}",
+            self.width, self.height, self.fps, self.pos_x, self.pos_y, self.scale, self.rotation
+        )?;
+        if let Some(mirror) = &self.mirror {
+            write!(f, " mirroring {mirror}")?;
+        }
+        Ok(())
+    }
+}
+
+/// monitor input that needs to be switched via ddc for a profile to be active
+#[derive(Debug)]
+pub struct InputChange {
+    pub monitor: String,
+    pub current: InputSource,
+    pub target: InputSource,
+}
+
+/// result of the layout computation of a profile, contains everything that is changed when applying the profile
+#[derive(Debug)]
+pub struct ProfileLayout {
+    pub monitors: Vec<(OutputId, SwayMonitor)>,
+    pub workspace_moves: Vec<(WorkspaceName, String)>,
+    pub workspace_rules: Vec<(WorkspaceName, Vec<String>)>,
+    pub window_commands: Vec<String>,
+    pub input_mappings: Vec<(String, String)>,
+    pub output_commands: Vec<String>,
+    pub bar_commands: Vec<String>,
+    pub waybar_outputs: Vec<String>,
+    pub input_changes: Vec<InputChange>,
+    pub violations: Vec<LayoutViolation>,
+    pub repaired: bool,
+}
+
+impl Display for ProfileLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Monitors:")?;
+        for (_, monitor) in &self.monitors {
+            writeln!(f, "  {monitor}")?;
+        }
+        writeln!(f, "Workspace moves:")?;
+        for (ws, output) in &self.workspace_moves {
+            writeln!(f, "  {ws} -> {output}")?;
+        }
+        writeln!(f, "Workspace rules:")?;
+        for (ws, outputs) in &self.workspace_rules {
+            writeln!(f, "  {ws} -> {}", outputs.join(", "))?;
+        }
+        writeln!(f, "Output options:")?;
+        for cmd in &self.output_commands {
+            writeln!(f, "  {cmd}")?;
+        }
+        writeln!(f, "Bars:")?;
+        for cmd in &self.bar_commands {
+            writeln!(f, "  {cmd}")?;
+        }
+        if !self.waybar_outputs.is_empty() {
+            writeln!(f, "  waybar -> {}", self.waybar_outputs.join(", "))?;
+        }
+        writeln!(f, "Input mappings:")?;
+        for (input, output) in &self.input_mappings {
+            writeln!(f, "  {input} -> {output}")?;
+        }
+        writeln!(f, "Window moves:")?;
+        for cmd in &self.window_commands {
+            writeln!(f, "  {cmd}")?;
+        }
+        writeln!(f, "Input changes:")?;
+        for input_change in &self.input_changes {
+            writeln!(
+                f,
+                "  {}: {:?} -> {:?}",
+                input_change.monitor, input_change.current, input_change.target
+            )?;
+        }
+        if !self.violations.is_empty() {
+            if self.repaired {
+                writeln!(f, "Repaired layout problems:")?;
+            } else {
+                writeln!(f, "Layout problems:")?;
+            }
+            for violation in &self.violations {
+                writeln!(f, "  {violation}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// output name of the enabled monitor referred to by a screen identifier or output name
+fn enabled_output<'a>(
+    sway_monitors: &'a [(OutputId, SwayMonitor)],
+    target: &str,
+) -> Option<&'a str> {
+    sway_monitors
+        .iter()
+        .find(|(_, hm)| hm.enabled && (hm.identifier == target || hm.name == target))
+        .map(|(_, hm)| hm.name.as_str())
+}
+
+/// command line run by the shell, so quoted arguments stay together
+pub fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+// run commands that where defined
+fn run_scripts(scripts: &[String]) {
+    for cmd in scripts {
+        if let Err(err) = shell_command(cmd).output() {
+            eprintln!("Could not run {cmd}: {err}");
+        }
+    }
+}
+
+/// answer of the compositor to a layout that was tested instead of applied
+#[derive(Debug, PartialEq)]
+pub enum LayoutTest {
+    Accepted,
+    Rejected(String),
+    /// the output backend can not test configurations
+    Untested,
+}
+
+/// send a layout to the output state thread and wait for the answer of the compositor
+///
+/// `None` if the output backend can not tell whether the layout was accepted. The backends answer
+/// or drop the configuration, so getting no answer at all is an error.
+fn configure_heads(
+    update_head_channel: &Sender<HeadConfiguration>,
+    monitors: Vec<(OutputId, SwayMonitor)>,
+    test: bool,
+) -> Option<anyhow::Result<()>> {
+    let (result_tx, result_rx) = mpsc::channel();
+    if update_head_channel
+        .send(HeadConfiguration {
+            monitors,
+            test,
+            result_tx,
+        })
+        .is_err()
+    {
+        return Some(Err(anyhow::anyhow!("the outputs are not managed anymore")));
+    }
+    // a dropped sender means the output backend does not report back
+    match result_rx.recv_timeout(Duration::from_millis(APPLY_TIMEOUT)) {
+        Ok(result) => Some(result),
+        Err(RecvTimeoutError::Timeout) => Some(Err(anyhow::anyhow!(
+            "no answer from the compositor to the output configuration"
+        ))),
+        Err(RecvTimeoutError::Disconnected) => None,
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Getters, Clone)]
@@ -133,27 +439,81 @@ pub struct ScreensProfile {
     screens: Vec<ScreenConfiguration>,
     #[serde(default)]
     scripts: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    on_failure: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workspaces: Option<WorkspaceDistribution>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    windows: Vec<WindowRule>,
+    #[serde(default, skip_serializing_if = "FloatingWindows::is_rescue")]
+    floating_windows: FloatingWindows,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    inputs: BTreeMap<String, InputSettings>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    bars: BTreeMap<String, Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    waybar: Option<WaybarOutputs>,
 }
 
 impl ScreensProfile {
+    /// build a profile from the layout currently active on the attached monitors
+    pub fn from_head_config(head_config: &HashMap<OutputId, MonitorInformation>) -> Self {
+        let monitors = head_config
+            .values()
+            .sorted_by_key(|monitor_info| monitor_info.name().clone())
+            .collect::<Vec<&MonitorInformation>>();
+        let positions = layout::infer_relative_positions(
+            &monitors
+                .iter()
+                .map(|monitor_info| {
+                    (
+                        monitor_info.identifier(),
+                        (monitor_info.enabled() != &0).then(|| monitor_info.layout_rect()),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        let screens = monitors
+            .into_iter()
+            .zip(positions)
+            .map(|(monitor_info, position)| ScreenConfiguration {
+                identifier: monitor_info.identifier(),
+                scale: *monitor_info.scale(),
+                rotation: (*monitor_info.transform()).into(),
+                display_output_code: MonitorInputSourceMatcher::Any,
+                wallpaper: PathBuf::new(),
+                position: Some(position),
+                constraints: Vec::new(),
+                workspaces: Vec::new(),
+                mapped_inputs: Vec::new(),
+                sway_output: SwayOutputOptions::default(),
+                enabled: monitor_info.enabled() != &0,
+            })
+            .collect();
+        Self {
+            screens,
+            scripts: Vec::new(),
+            on_failure: Vec::new(),
+            workspaces: None,
+            windows: Vec::new(),
+            floating_windows: FloatingWindows::default(),
+            inputs: BTreeMap::new(),
+            bars: BTreeMap::new(),
+            waybar: None,
+        }
+    }
+
     /// check if a profile matches the current screens connected to the device
     pub fn is_connected(
         &self,
-        head_config: &HashMap<ObjectId, MonitorInformation>,
+        head_config: &HashMap<OutputId, MonitorInformation>,
         current_monitor_inputs: &BTreeMap<String, InputSource>,
     ) -> bool {
         let mut connected = true;
         for screen in &self.screens {
             let mut screen_found = false;
             for (_id, monitor_info) in head_config.iter() {
-                if screen.identifier() == monitor_info.name()
-                    || screen.identifier()
-                        == &format!(
-                            "{} {}",
-                            monitor_info.make(),
-                            monitor_info.serial().as_ref().unwrap_or(&"".to_string())
-                        )
-                {
+                if monitor_info.matches(screen.identifier()) {
                     if let Some(source) = current_monitor_inputs.get(monitor_info.name()) {
                         // if we have information about the current monitor selected input
                         // then only consider it connected if the profiles input matches
@@ -192,43 +552,38 @@ impl ScreensProfile {
         weight
     }
 
-    pub fn apply(
+    /// compute the layout this profile would result in without changing anything
+    pub fn layout(
         &self,
-        head_config: &HashMap<ObjectId, MonitorInformation>,
-        update_head_channel: &mut Sender<Vec<(ObjectId, SwayMonitor)>>,
-    ) {
+        head_config: &HashMap<OutputId, MonitorInformation>,
+        current_monitor_inputs: &BTreeMap<String, InputSource>,
+        layout_check: &LayoutCheck,
+    ) -> Result<ProfileLayout, LayoutError> {
         // match connected monitor information with profile monitor configuration
         let mut monitor_map: BTreeMap<
             &str,
-            (&ScreenConfiguration, &MonitorInformation, &ObjectId),
+            (&ScreenConfiguration, &MonitorInformation, &OutputId),
         > = BTreeMap::new();
+        let mut input_changes = Vec::new();
         for screen in &self.screens {
             for (id, monitor_info) in head_config.iter() {
-                if screen.identifier() == monitor_info.name()
-                    || screen.identifier()
-                        == &format!(
-                            "{} {}",
-                            monitor_info.make(),
-                            monitor_info.serial().as_ref().unwrap_or(&"".to_string())
-                        )
-                {
+                if monitor_info.matches(screen.identifier()) {
                     monitor_map.insert(screen.identifier(), (screen, monitor_info, id));
-                    if let Some(mut monitor_device) =
-                        Monitor::enumerate().find(|mon| *monitor_info.name() == mon.handle.name())
-                    {
-                        match screen.display_output_code() {
-                            MonitorInputSourceMatcher::Any => { /* nothing to do here */ }
-                            MonitorInputSourceMatcher::Input(sould_be_input) => {
-                                // if applied profile monitor config specifies a monitor input
-                                // make sure it is configured correctly!
-                                let _ =
-                                    monitor_device.get_input_source().and_then(|current_input| {
-                                        if current_input != *sould_be_input {
-                                            let _ =
-                                                monitor_device.set_input_source(*sould_be_input);
-                                        }
-                                        Ok(())
+                    match screen.display_output_code() {
+                        MonitorInputSourceMatcher::Any => { /* nothing to do here */ }
+                        MonitorInputSourceMatcher::Input(sould_be_input) => {
+                            // if applied profile monitor config specifies a monitor input
+                            // make sure it is configured correctly!
+                            if let Some(current_input) =
+                                current_monitor_inputs.get(monitor_info.name())
+                            {
+                                if current_input != sould_be_input {
+                                    input_changes.push(InputChange {
+                                        monitor: monitor_info.name().to_string(),
+                                        current: *current_input,
+                                        target: *sould_be_input,
                                     });
+                                }
                             }
                         }
                     }
@@ -236,23 +591,31 @@ impl ScreensProfile {
             }
         }
 
-        // build tree of attached displays
-        let mut position_tree = TreeBuilder::new().with_root(Node::new("Root")).build();
-        let mut already_added: Vec<&str> = Vec::new();
-        for (ident, (_conf, _info, _id)) in monitor_map.iter() {
-            add_node_to_tree(ident, &mut position_tree, &monitor_map, &mut already_added);
-        }
+        // place the attached displays according to their position constraints
+        let positions = layout::solve(
+            &monitor_map
+                .iter()
+                .map(|(ident, (conf, info, _id))| SolverScreen {
+                    identifier: ident,
+                    size: if *conf.enabled() {
+                        conf.rotation().transform_size(*info.preffered_mode().size())
+                    } else {
+                        (0, 0)
+                    },
+                    constraints: conf.position_constraints(),
+                })
+                .collect::<Vec<SolverScreen>>(),
+        )?;
 
         let mut sway_monitors = Vec::new();
-        for (ident, (conf, info, _id)) in monitor_map.iter() {
-            let position = calc_screen_pixel_positon(ident, &position_tree, &monitor_map);
+        for ((ident, (conf, info, _id)), position) in monitor_map.iter().zip(positions) {
             sway_monitors.push((
                 (*_id).clone(),
                 SwayMonitor {
-                    mirror: match conf.position() {
-                        ScreenPositionRelative::Mirror(parent) => Some(parent.to_string()),
-                        _ => None,
-                    },
+                    identifier: ident.to_string(),
+                    mirror: conf.position_constraints().iter().find_map(|constraint| {
+                        constraint.mirrored().map(|parent| parent.to_string())
+                    }),
                     enabled: *conf.enabled(),
                     name: info.name().to_string(),
                     width: info.preffered_mode().size().0,
@@ -262,14 +625,18 @@ impl ScreensProfile {
                     pos_y: position.1,
                     scale: *conf.scale(),
                     rotation: conf.rotation().clone(),
-                    workspaces: conf.workspaces().clone(),
+                    workspaces: conf
+                        .workspaces()
+                        .iter()
+                        .map(|assignment| assignment.name().clone())
+                        .collect(),
                 },
             ));
         }
 
         // repostion montiors so that all coordinates are postive (why hyprland?)
-        let min_pos_x = sway_monitors.iter().map(|(_, hm)| hm.pos_x).min().unwrap();
-        let min_pos_y = sway_monitors.iter().map(|(_, hm)| hm.pos_y).min().unwrap();
+        let min_pos_x = sway_monitors.iter().map(|(_, hm)| hm.pos_x).min().unwrap_or(0);
+        let min_pos_y = sway_monitors.iter().map(|(_, hm)| hm.pos_y).min().unwrap_or(0);
         sway_monitors = sway_monitors
             .into_iter()
             .map(|(id, mut hm)| {
@@ -279,200 +646,754 @@ impl ScreensProfile {
             })
             .collect();
 
-        // write hyprland configuration file
-        let mut moved_workspaces = Vec::new();
-        let _ = swayipc::Connection::new().and_then(|mut sway_ipc| {
-            let current_ws = sway_ipc
-                .get_workspaces()
-                .expect("sway is expected to run and have workspaces")
-                .into_iter()
-                .find_or_first(|ws| ws.focused);
-            for (_, hm) in &sway_monitors {
+        // check the final rectangles of the enabled monitors before anything gets applied
+        let enabled_monitors = sway_monitors
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, hm))| hm.enabled)
+            .map(|(index, _)| index)
+            .collect::<Vec<usize>>();
+        let placed_screens = enabled_monitors
+            .iter()
+            .map(|index| {
+                let hm = &sway_monitors[*index].1;
+                PlacedScreen {
+                    identifier: &hm.identifier,
+                    rect: hm.rect(),
+                    mirror: hm.mirror.as_deref(),
+                }
+            })
+            .collect::<Vec<PlacedScreen>>();
+        let mut violations = layout::validate(&placed_screens);
+        let mut repaired = false;
+        if !violations.is_empty() && *layout_check == LayoutCheck::Repair {
+            let positions = layout::repair(&placed_screens);
+            // problems the repair could not fix are reported instead
+            let remaining = layout::validate(
+                &placed_screens
+                    .iter()
+                    .zip(&positions)
+                    .map(|(screen, (x, y))| PlacedScreen {
+                        rect: Rect::new(*x, *y, screen.rect.width, screen.rect.height),
+                        ..screen.clone()
+                    })
+                    .collect::<Vec<PlacedScreen>>(),
+            );
+            repaired = remaining.is_empty();
+            if repaired {
+                for (index, position) in enabled_monitors.into_iter().zip(positions) {
+                    let hm = &mut sway_monitors[index].1;
+                    (hm.pos_x, hm.pos_y) = position;
+                }
+            } else {
+                violations = remaining;
+            }
+        }
+
+        let mut workspace_moves: Vec<(WorkspaceName, String)> = Vec::new();
+        let mut workspace_rules: Vec<(WorkspaceName, Vec<String>)> = Vec::new();
+        for (_, hm) in &sway_monitors {
+            let (conf, _, _) = monitor_map[hm.identifier.as_str()];
+            for assignment in conf.workspaces() {
+                let ws = assignment.name();
+                if workspace_rules.iter().any(|(ruled_ws, _)| ruled_ws == ws) {
+                    println!("Workspace {ws} already bound to different monitor! Ignoring …");
+                    continue;
+                }
+                // fallback outputs are given as profile identifiers or output names
+                let fallbacks = assignment
+                    .fallback()
+                    .iter()
+                    .map(|fallback| {
+                        head_config
+                            .values()
+                            .find(|monitor_info| monitor_info.matches(fallback))
+                            .map(|monitor_info| monitor_info.name().clone())
+                            .unwrap_or(fallback.clone())
+                    })
+                    .collect::<Vec<String>>();
                 if hm.enabled {
-                    for ws in &hm.workspaces {
-                        if moved_workspaces.contains(ws) {
-                            println!(
-                                "Workspace {ws} already bound to different monitor! Ignoring …"
-                            );
-                        } else {
-                            //TODO check if sway dispatch works as expected
-                            if let Some(sway_ws) = sway_ipc
-                                .get_workspaces()
-                                .expect("sway is expected to run and have workspaces")
-                                .iter()
-                                .find_or_first(|sway_ws| sway_ws.num == *ws as i32)
-                            {
-                                let to_workspace_cmd = swayipc_command_builder::Command::new()
-                                    .workspace()
-                                    .goto()
-                                    .name(sway_ws.name.clone());
-                                let move_workspace_cmd = swayipc_command_builder::Command::new()
-                                    .sway_move()
-                                    .workspace()
-                                    .to()
-                                    .output()
-                                    .with()
-                                    .name(&hm.name);
-                                let _ = sway_ipc.run_command(to_workspace_cmd);
-                                let _ = sway_ipc.run_command(move_workspace_cmd);
-                                moved_workspaces.push(*ws);
-                            }
-                        }
+                    workspace_moves.push((ws.clone(), hm.name.clone()));
+                    let mut outputs = vec![hm.name.clone()];
+                    outputs.extend(fallbacks);
+                    workspace_rules.push((ws.clone(), outputs));
+                } else if !fallbacks.is_empty() {
+                    // the workspaces of a disabled screen go to its first enabled fallback
+                    if let Some(fallback) = fallbacks.iter().find(|output| {
+                        sway_monitors
+                            .iter()
+                            .any(|(_, other)| other.enabled && &other.name == *output)
+                    }) {
+                        workspace_moves.push((ws.clone(), fallback.clone()));
                     }
+                    workspace_rules.push((ws.clone(), fallbacks));
                 }
             }
-            // move back to previously active workspace
-            if let Some(current_ws) = current_ws {
-                let to_workspace_cmd = swayipc_command_builder::Command::new()
-                    .workspace()
-                    .goto()
-                    .name(current_ws.name);
-                let _ = sway_ipc.run_command(to_workspace_cmd);
+        }
+
+        // spread the remaining workspaces of the range over the enabled screens from left to right,
+        // mirrored screens show the workspaces of their parent
+        if let Some(distribution) = &self.workspaces {
+            let remaining_ws = distribution
+                .range
+                .names()
+                .into_iter()
+                .filter(|ws| {
+                    !workspace_moves.iter().any(|(moved_ws, _)| {
+                        ws.matches(moved_ws.as_str(), moved_ws.number().unwrap_or(-1))
+                    })
+                })
+                .collect::<Vec<WorkspaceName>>();
+            let screen_order = sway_monitors
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, hm))| hm.enabled && hm.mirror.is_none())
+                .sorted_by_key(|(_, (_, hm))| (hm.pos_x, hm.pos_y))
+                .map(|(index, _)| index)
+                .collect::<Vec<usize>>();
+            let distributed = workspaces::distribute(
+                &remaining_ws,
+                &screen_order
+                    .iter()
+                    .map(|index| sway_monitors[*index].1.rect().width)
+                    .collect::<Vec<i32>>(),
+                distribution.strategy,
+            );
+            for (index, screen_ws) in screen_order.into_iter().zip(distributed) {
+                let hm = &mut sway_monitors[index].1;
+                for ws in screen_ws {
+                    workspace_moves.push((ws.clone(), hm.name.clone()));
+                    workspace_rules.push((ws.clone(), vec![hm.name.clone()]));
+                    hm.workspaces.push(ws);
+                }
             }
-            Ok(())
-        });
+        }
 
-        let _ = update_head_channel.send(sway_monitors);
+        let output_commands = sway_monitors
+            .iter()
+            .filter(|(_, hm)| hm.enabled)
+            .flat_map(|(_, hm)| {
+                let (conf, _, _) = monitor_map[hm.identifier.as_str()];
+                conf.sway_output().commands(&hm.name)
+            })
+            .collect();
 
-        // run commands that where defined
-        for cmd in &self.scripts {
-            let args = cmd.split(' ').collect::<Vec<&str>>();
-            let _out = Command::new(args[0]).args(&args[1..]).output().unwrap();
-        }
-    }
-}
+        // touchscreens and tablets only cover the screen they belong to
+        let input_mappings = sway_monitors
+            .iter()
+            .filter(|(_, hm)| hm.enabled)
+            .flat_map(|(_, hm)| {
+                let (conf, _, _) = monitor_map[hm.identifier.as_str()];
+                conf.mapped_inputs()
+                    .iter()
+                    .map(|input| (inputs::selector(input), hm.name.clone()))
+            })
+            .collect();
 
-fn calc_screen_pixel_positon(
-    ident: &str,
-    position_tree: &Tree<&str>,
-    monitor_map: &BTreeMap<&str, (&ScreenConfiguration, &MonitorInformation, &ObjectId)>,
-) -> (i32, i32) {
-    let root_node_id = position_tree.root_node_id().unwrap();
-    let current_node_id = find_nodeid_from_ident(ident, position_tree).unwrap();
-    position_tree
-        .get(&current_node_id)
-        .and_then(|current_node| {
-            if current_node.parent().unwrap() == root_node_id {
-                // if multiple screens are attached to root then the profile is broken and the resulting configuration may look broken!
-                Ok((0, 0))
-            } else {
-                let parent_ident = position_tree
-                    .get(current_node.parent().unwrap())
-                    .unwrap()
-                    .data();
-                let parent_position =
-                    calc_screen_pixel_positon(&parent_ident, position_tree, monitor_map);
-                let (conf, info, _id) = monitor_map.get(ident).unwrap();
-                let (parent_conf, parent_info, _id) = monitor_map.get(parent_ident).unwrap();
-                let parent_size = if parent_conf.enabled {
-                    parent_conf
-                        .rotation()
-                        .transform_size(*parent_info.preffered_mode().size())
-                } else {
-                    (0, 0)
+        // window rules targeting screens that are missing or disabled are skipped
+        let window_commands = self
+            .windows
+            .iter()
+            .flat_map(|rule| {
+                let output = match &rule.target {
+                    WindowTarget::Output(target) => enabled_output(&sway_monitors, target),
+                    WindowTarget::Workspace(_) => None,
                 };
-                let own_size = if conf.enabled {
-                    conf.rotation()
-                        .transform_size(*info.preffered_mode().size())
+                rule.commands(output)
+            })
+            .collect();
+
+        // bars are limited to the listed screens that are enabled
+        let bar_commands = self
+            .bars
+            .iter()
+            .flat_map(|(bar_id, targets)| {
+                let outputs = targets
+                    .iter()
+                    .filter_map(|target| enabled_output(&sway_monitors, target))
+                    .map(|output| output.to_string())
+                    .collect::<Vec<String>>();
+                if outputs.is_empty() {
+                    Vec::new()
                 } else {
-                    (0, 0)
-                };
-                let offset = conf.position().offset(parent_size, own_size);
-                Ok((parent_position.0 + offset.0, parent_position.1 + offset.1))
-            }
+                    bars::bar_commands(bar_id, &outputs)
+                }
+            })
+            .collect();
+        let waybar_outputs = self
+            .waybar
+            .iter()
+            .flat_map(|waybar| &waybar.outputs)
+            .filter_map(|target| enabled_output(&sway_monitors, target))
+            .map(|output| output.to_string())
+            .collect();
+
+        Ok(ProfileLayout {
+            monitors: sway_monitors,
+            workspace_moves,
+            workspace_rules,
+            window_commands,
+            input_mappings,
+            output_commands,
+            bar_commands,
+            waybar_outputs,
+            input_changes,
+            violations,
+            repaired,
         })
-        .unwrap()
-}
+    }
 
-fn find_nodeid_from_ident(ident: &str, position_tree: &Tree<&str>) -> Option<NodeId> {
-    for node_id in position_tree
-        .traverse_level_order_ids(position_tree.root_node_id().unwrap())
-        .unwrap()
-    {
-        if position_tree.get(&node_id).unwrap().data() == &ident {
-            return Some(node_id.clone());
+    pub fn apply(
+        &self,
+        head_config: &HashMap<OutputId, MonitorInformation>,
+        current_monitor_inputs: &BTreeMap<String, InputSource>,
+        config: &AppConfiguration,
+        previous_outputs: &[Rect],
+        input_restore: &mut BTreeMap<String, InputSettings>,
+        update_head_channel: &mut Sender<HeadConfiguration>,
+    ) -> anyhow::Result<()> {
+        let layout_check = config.layout_check();
+        let layout = self.layout(head_config, current_monitor_inputs, layout_check)?;
+        if !layout.violations.is_empty() {
+            let violations = layout.violations.iter().join(", ");
+            match layout_check {
+                LayoutCheck::Warn => eprintln!("Layout has problems: {violations}"),
+                LayoutCheck::Refuse => {
+                    anyhow::bail!("Refusing to apply layout with problems: {violations}")
+                }
+                LayoutCheck::Repair if layout.repaired => {
+                    eprintln!("Repaired layout problems: {violations}")
+                }
+                LayoutCheck::Repair => {
+                    anyhow::bail!("Could not repair layout problems: {violations}")
+                }
+            }
         }
-    }
-    None
-}
 
-fn add_node_to_tree<'a>(
-    ident: &'a str,
-    position_tree: &mut Tree<&'a str>,
-    monitor_map: &BTreeMap<
-        &'a str,
-        (
-            &'a ScreenConfiguration,
-            &'a MonitorInformation,
-            &'a ObjectId,
-        ),
-    >,
-    already_added: &mut Vec<&'a str>,
-) -> Option<NodeId> {
-    // if monitor was already added do not add it again!
-    if !already_added.contains(&ident) {
-        monitor_map.get(&ident).and_then(|(conf, _info, _id)| {
-            let parent_ident = conf.position().parent();
-            match parent_ident {
-                Some(parent) => {
-                    match monitor_map.get(parent) {
-                        Some(_) => {
-                            let parent_node_id =
-                                add_node_to_tree(parent, position_tree, monitor_map, already_added)
-                                    .unwrap();
-                            let node = position_tree
-                                .insert(
-                                    Node::new(ident),
-                                    id_tree::InsertBehavior::UnderNode(&parent_node_id),
-                                )
-                                .unwrap();
-                            already_added.push(ident);
-                            Some(node)
-                        }
-                        None => {
-                            // if the parent indentifier is not found in the configuration then attach it to root
-                            let node = position_tree
-                                .insert(
-                                    Node::new(ident),
-                                    id_tree::InsertBehavior::UnderNode(
-                                        &position_tree.root_node_id().unwrap().clone(),
-                                    ),
-                                )
-                                .unwrap();
-                            already_added.push(ident);
-                            Some(node)
-                        }
-                    }
+        for input_change in &layout.input_changes {
+            if let Some(mut monitor_device) =
+                Monitor::enumerate().find(|mon| input_change.monitor == mon.handle.name())
+            {
+                let _ = monitor_device.set_input_source(input_change.target);
+            }
+        }
+
+        if let OutputMode::File(config_file) = config.output_mode() {
+            config_file.write(&layout)?;
+            self.apply_inputs(config, &[], input_restore);
+            run_scripts(&self.scripts);
+            return Ok(());
+        }
+
+        let mut backend = backend::connect(*config.backend());
+        if let Some(backend) = backend.as_mut() {
+            // rules make sure workspaces that do not exist yet are created on the right output
+            for (ws, outputs) in &layout.workspace_rules {
+                let _ = backend.assign_workspace(ws, outputs);
+            }
+            if let Ok(current_workspaces) = backend.workspaces() {
+                backend::execute(
+                    backend.as_mut(),
+                    &workspaces::plan_moves(
+                        &current_workspaces,
+                        &layout.workspace_moves,
+                        *config.missing_workspaces(),
+                        &[],
+                    ),
+                );
+            }
+        }
+
+        let outputs = layout
+            .monitors
+            .iter()
+            .filter(|(_, hm)| hm.enabled)
+            .map(|(_, hm)| hm.logical_rect())
+            .collect::<Vec<Rect>>();
+        // hyprland and niri configure their outputs themselves, everything else goes over the
+        // output state thread
+        let configured = backend.as_mut().is_some_and(|backend| {
+            let monitors = layout
+                .monitors
+                .iter()
+                .map(|(_, hm)| hm.clone())
+                .collect::<Vec<SwayMonitor>>();
+            backend.configure_outputs(&monitors).unwrap_or_else(|err| {
+                eprintln!(
+                    "Could not configure outputs through {}: {err:#}",
+                    backend.name()
+                );
+                false
+            })
+        });
+        if !configured {
+            if let Some(Err(err)) =
+                configure_heads(update_head_channel, layout.monitors.clone(), false)
+            {
+                run_scripts(&self.on_failure);
+                return Err(err.context("Could not configure the outputs"));
+            }
+        }
+
+        // sway output options, windows and inputs follow once the outputs are configured
+        if let Some(backend) = backend.as_mut() {
+            let mut commands = layout.output_commands.clone();
+            commands.extend(layout.bar_commands.iter().cloned());
+            commands.extend(layout.window_commands.iter().cloned());
+            if self.floating_windows.is_rescue() {
+                if let Ok(floating_windows) = backend.floating_windows() {
+                    commands.extend(windows::rescue_commands(
+                        &floating_windows,
+                        previous_outputs,
+                        &outputs,
+                    ));
                 }
-                None => {
-                    // No parent means this monitor is the Root display
-                    let node = position_tree
-                        .insert(
-                            Node::new(ident),
-                            id_tree::InsertBehavior::UnderNode(
-                                &position_tree.root_node_id().unwrap().clone(),
-                            ),
-                        )
-                        .unwrap();
-                    already_added.push(ident);
-                    Some(node)
+            }
+            if let Err(err) = backend.run_commands(&commands) {
+                eprintln!("{err:#}");
+            }
+        }
+        self.apply_inputs(config, &layout.input_mappings, input_restore);
+
+        if let Some(waybar) = &self.waybar {
+            if let Err(err) = waybar.update(&layout.waybar_outputs) {
+                eprintln!("Could not update waybar outputs: {err}");
+            }
+        }
+
+        run_scripts(&self.scripts);
+        Ok(())
+    }
+
+    /// ask the compositor if it would accept the layout of this profile without applying it
+    pub fn test(
+        &self,
+        head_config: &HashMap<OutputId, MonitorInformation>,
+        current_monitor_inputs: &BTreeMap<String, InputSource>,
+        config: &AppConfiguration,
+        update_head_channel: &Sender<HeadConfiguration>,
+    ) -> anyhow::Result<LayoutTest> {
+        let layout = self.layout(head_config, current_monitor_inputs, config.layout_check())?;
+        if !config.output_mode().is_live() {
+            return Ok(LayoutTest::Untested);
+        }
+        Ok(
+            match configure_heads(update_head_channel, layout.monitors, true) {
+                Some(Ok(())) => LayoutTest::Accepted,
+                Some(Err(err)) => LayoutTest::Rejected(format!("{err:#}")),
+                None => LayoutTest::Untested,
+            },
+        )
+    }
+
+    /// configure the sway input devices for this profile and restore settings of the previous one
+    ///
+    /// `input_mappings` are the input selectors mapped to outputs by the layout.
+    fn apply_inputs(
+        &self,
+        config: &AppConfiguration,
+        input_mappings: &[(String, String)],
+        input_restore: &mut BTreeMap<String, InputSettings>,
+    ) {
+        let mut profile_inputs = self.inputs.clone();
+        for (input, output) in input_mappings {
+            profile_inputs
+                .entry(input.clone())
+                .or_default()
+                .map_to_output = Some(output.clone());
+        }
+        if let Some(mut backend) = backend::connect(*config.backend()) {
+            if let Ok(sway_inputs) = backend.inputs() {
+                let commands = inputs::apply_commands(
+                    input_restore,
+                    &sway_inputs,
+                    &profile_inputs,
+                    config.input_defaults(),
+                );
+                if let Err(err) = backend.run_commands(&commands) {
+                    eprintln!("{err:#}");
                 }
             }
-        })
-    } else {
-        find_nodeid_from_ident(ident, position_tree)
+        }
+    }
+}
+
+/// source of the connected outputs and the way their configuration is applied
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputBackend {
+    /// wlr output management, the ipc of sway or niri, kde output management or the display
+    /// configuration of mutter is used if the compositor does not offer it, randr in xorg sessions
+    #[default]
+    Wlr,
+    /// `get_outputs` and `output` commands over the sway ipc socket
+    Sway,
+    /// `Outputs` and `Output` requests over the niri ipc socket
+    Niri,
+    /// kde output management of kwin
+    Kde,
+    /// `org.gnome.Mutter.DisplayConfig` d-bus api
+    Gnome,
+    /// randr extension of the x server, for i3 and other xorg sessions
+    X11,
+}
+
+impl OutputBackend {
+    pub fn is_wlr(&self) -> bool {
+        *self == OutputBackend::Wlr
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Getters, Clone)]
 pub struct AppConfiguration {
     profiles: BTreeMap<String, ScreensProfile>,
+    #[serde(default)]
+    layout_check: LayoutCheck,
+    #[serde(default)]
+    missing_workspaces: MissingWorkspaces,
+    /// compositor managing the workspaces, detected from the environment if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backend: Option<BackendKind>,
+    /// protocol the outputs are followed and configured with
+    #[serde(default, skip_serializing_if = "OutputBackend::is_wlr")]
+    output_backend: OutputBackend,
+    /// write the layout into a compositor configuration file instead of applying it live
+    #[serde(default, skip_serializing_if = "OutputMode::is_live")]
+    output_mode: OutputMode,
+    /// let the compositor test the best matching profile on hotplug and fall back to the next
+    /// one if it is rejected
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    test_profiles: bool,
+    /// input settings restored when a profile stops changing them, for settings sway does not report
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    input_defaults: BTreeMap<String, InputSettings>,
+}
+
+impl AppConfiguration {
+    /// all profiles matching the connected monitors, the best match (highest weight) comes first
+    pub fn matching_profiles(
+        &self,
+        head_config: &HashMap<OutputId, MonitorInformation>,
+        current_monitor_inputs: &BTreeMap<String, InputSource>,
+    ) -> Vec<(&String, &ScreensProfile)> {
+        self.profiles
+            .iter()
+            .filter(|(name, profile)| {
+                eprintln!("Checking if profile {} is connected", name);
+                profile.is_connected(head_config, current_monitor_inputs)
+            })
+            .sorted_by_key(|profile| profile.1.weight()) // rate matching profiles
+            .rev() // profile with highest weight should be first
+            .collect()
+    }
+
+    pub fn add_profile(&mut self, name: String, profile: ScreensProfile) {
+        self.profiles.insert(name, profile);
+    }
+}
+
+/// insert a profile into the `profiles` section of the configuration file text
+///
+/// The text is edited line based instead of re-serializing the whole configuration so that
+/// comments and formatting of the existing profiles stay untouched.
+pub fn insert_profile_into_config(
+    config_text: &str,
+    name: &str,
+    profile: &ScreensProfile,
+) -> Result<String, serde_yaml::Error> {
+    let profile_yaml = serde_yaml::to_string(&BTreeMap::from([(name, profile)]))?;
+    let indented_profile = profile_yaml
+        .lines()
+        .map(|line| format!("  {line}\n"))
+        .collect::<String>();
+
+    let lines = config_text.lines().collect::<Vec<&str>>();
+    let profiles_start = lines.iter().position(|line| {
+        line.split('#').next().unwrap_or_default().trim_end() == "profiles:"
+    });
+    let result = match profiles_start {
+        Some(start) => {
+            // the profiles section ends where the next top level key starts
+            let end = lines
+                .iter()
+                .enumerate()
+                .skip(start + 1)
+                .find(|(_, line)| {
+                    !line.is_empty() && !line.starts_with([' ', '\t', '#', '-'])
+                })
+                .map(|(index, _)| index)
+                .unwrap_or(lines.len());
+            let mut result = lines[..end]
+                .iter()
+                .map(|line| format!("{line}\n"))
+                .collect::<String>();
+            result.push_str(&indented_profile);
+            for line in &lines[end..] {
+                result.push_str(line);
+                result.push('\n');
+            }
+            result
+        }
+        None => {
+            let mut result = config_text.to_string();
+            if !result.is_empty() && !result.ends_with('\n') {
+                result.push('\n');
+            }
+            result.push_str("profiles:\n");
+            result.push_str(&indented_profile);
+            result
+        }
+    };
+    Ok(result)
 }
 
 impl Default for AppConfiguration {
     fn default() -> Self {
         Self {
             profiles: BTreeMap::new(),
+            layout_check: LayoutCheck::default(),
+            missing_workspaces: MissingWorkspaces::default(),
+            backend: None,
+            output_backend: OutputBackend::default(),
+            output_mode: OutputMode::default(),
+            test_profiles: false,
+            input_defaults: BTreeMap::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        configure_heads, insert_profile_into_config, shell_command, ScreenRotation, ScreensProfile,
+        SwayMonitor, SwayOutputOptions,
+    };
+    use crate::layout::{LayoutCheck, Rect};
+    use crate::windows::{self, FloatingWindows};
+    use crate::wlr_output_state::{
+        HeadConfiguration, MonitorInformation, MonitorInformationBuilder, MonitorModeBuilder,
+        OutputId,
+    };
+    use crate::workspaces::WorkspaceName;
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::mpsc,
+        thread,
+    };
+    use wayland_client::protocol::wl_output::Transform;
+
+    fn monitor(name: &str, make: &str, serial: &str) -> (OutputId, MonitorInformation) {
+        let mut builder = MonitorInformationBuilder::default();
+        builder
+            .name(name)
+            .make(make)
+            .serial(Some(serial.to_string()))
+            .enabled(1)
+            .transform(Transform::Normal)
+            .scale(1.)
+            .add_mode(
+                MonitorModeBuilder::default()
+                    .size((1920, 1080))
+                    .refresh(60000)
+                    .preferred(true)
+                    .build()
+                    .unwrap(),
+            );
+        (OutputId::Name(name.to_string()), builder.build().unwrap())
+    }
+
+    const CONFIG: &str = "profiles: # named profiles
+  laptop:
+    screens:
+    - identifier: eDP-1 # built in display
+      scale: 1.0
+      rotation: Landscape
+      position: Root
+      enabled: true
+";
+
+    #[test]
+    fn insert_profile_keeps_comments() {
+        let profile = ScreensProfile {
+            screens: Vec::new(),
+            scripts: Vec::new(),
+            on_failure: Vec::new(),
+            workspaces: None,
+            windows: Vec::new(),
+            floating_windows: FloatingWindows::default(),
+            inputs: BTreeMap::new(),
+            bars: BTreeMap::new(),
+            waybar: None,
+        };
+        let config = insert_profile_into_config(CONFIG, "docked", &profile).unwrap();
+        assert!(config.starts_with(CONFIG));
+        assert!(config.ends_with("  docked:\n    screens: []\n    scripts: []\n"));
+
+        let config = insert_profile_into_config(&format!("{CONFIG}other: 1\n"), "docked", &profile)
+            .unwrap();
+        assert!(config.ends_with("    scripts: []\nother: 1\n"));
+    }
+
+    #[test]
+    fn rescue_windows_on_scaled_outputs() {
+        let monitor = SwayMonitor {
+            identifier: "Dell Inc. XXXXXXA".to_string(),
+            mirror: None,
+            enabled: true,
+            name: "DP-1".to_string(),
+            width: 3840,
+            height: 2160,
+            fps: 60.,
+            pos_x: 0,
+            pos_y: 0,
+            scale: 2.,
+            rotation: ScreenRotation::Landscape,
+            workspaces: Vec::new(),
+        };
+        assert_eq!(monitor.logical_rect(), Rect::new(0, 0, 1920, 1080));
+        // the window would still be on the output if its mode size was taken
+        let previous_outputs = [Rect::new(0, 0, 1920, 1080), Rect::new(1920, 0, 1920, 1080)];
+        assert_eq!(
+            windows::rescue_commands(
+                &[(7, Rect::new(2000, 100, 400, 300))],
+                &previous_outputs,
+                &[monitor.logical_rect()],
+            ),
+            ["[con_id=7] move absolute position 80 100"]
+        );
+    }
+
+    #[test]
+    fn saved_profile_reproduces_scaled_layout() {
+        let scaled_monitor = |name: &str, position: (i32, i32), size: (i32, i32), scale: f64| {
+            let monitor_info = MonitorInformationBuilder::default()
+                .name(name)
+                .enabled(1)
+                .position(position)
+                .transform(Transform::Normal)
+                .scale(scale)
+                .add_mode(
+                    MonitorModeBuilder::default()
+                        .size(size)
+                        .refresh(60000)
+                        .preferred(true)
+                        .build()
+                        .unwrap(),
+                )
+                .build()
+                .unwrap();
+            (OutputId::Name(name.to_string()), monitor_info)
+        };
+        let head_config = HashMap::from([
+            scaled_monitor("eDP-1", (0, 0), (3840, 2160), 2.),
+            scaled_monitor("DP-1", (3840, 2160), (1920, 1080), 1.),
+        ]);
+
+        let profile = ScreensProfile::from_head_config(&head_config);
+        let layout = profile
+            .layout(&head_config, &BTreeMap::new(), &LayoutCheck::Warn)
+            .unwrap();
+        assert_eq!(
+            layout
+                .monitors
+                .iter()
+                .map(|(_, hm)| (hm.name.as_str(), hm.pos_x, hm.pos_y))
+                .collect::<Vec<_>>(),
+            [("DP-1", 3840, 2160), ("eDP-1", 0, 0)]
+        );
+    }
+
+    #[test]
+    fn sway_output_commands() {
+        let options: SwayOutputOptions = serde_yaml::from_str(
+            "{ subpixel: vrgb, max_render_time: 0, allow_tearing: true, color_profile: /tmp/x.icc }",
+        )
+        .unwrap();
+        assert_eq!(
+            options.commands("DP-1"),
+            [
+                "output \"DP-1\" subpixel vrgb",
+                "output \"DP-1\" max_render_time off",
+                "output \"DP-1\" allow_tearing yes",
+                "output \"DP-1\" color_profile icc \"/tmp/x.icc\"",
+            ]
+        );
+        assert!(SwayOutputOptions::default().commands("DP-1").is_empty());
+
+        let options: SwayOutputOptions = serde_yaml::from_str("render_bit_depth: 10").unwrap();
+        assert_eq!(
+            options.commands("DP-1"),
+            ["output \"DP-1\" render_bit_depth 10"]
+        );
+        assert!(serde_yaml::from_str::<SwayOutputOptions>("render_bit_depth: 12").is_err());
+    }
+
+    #[test]
+    fn configure_heads_answers() {
+        let (head_tx, head_rx) = mpsc::channel::<HeadConfiguration>();
+        let output_thread = thread::spawn(move || {
+            for configuration in head_rx {
+                if configuration.test {
+                    let _ = configuration
+                        .result_tx
+                        .send(Err(anyhow::anyhow!("mode not supported")));
+                }
+            }
+        });
+        assert_eq!(
+            configure_heads(&head_tx, Vec::new(), true)
+                .map(|result| result.map_err(|err| err.to_string())),
+            Some(Err("mode not supported".to_string()))
+        );
+        // backends that do not report back drop the sender
+        assert!(configure_heads(&head_tx, Vec::new(), false).is_none());
+        drop(head_tx);
+        output_thread.join().unwrap();
+    }
+
+    #[test]
+    fn shell_command_keeps_quoted_arguments() {
+        let output = shell_command(r#"printf '%s|' "could not apply" profile"#)
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, b"could not apply|profile|");
+    }
+
+    #[test]
+    fn disabled_screen_workspaces_use_fallback() {
+        let profile: ScreensProfile = serde_yaml::from_str(
+            "screens:
+- identifier: Dell XXXXXXA
+  scale: 1.0
+  rotation: Landscape
+  position: Root
+  enabled: true
+- identifier: eDP-1
+  scale: 1.0
+  rotation: Landscape
+  position: !RightOver Dell XXXXXXA
+  workspaces:
+  - 1
+  - name: mail
+    fallback: [Dell XXXXXXA]
+  enabled: false
+",
+        )
+        .unwrap();
+        let head_config = HashMap::from([
+            monitor("DP-1", "Dell", "XXXXXXA"),
+            monitor("eDP-1", "BOE", ""),
+        ]);
+        let layout = profile
+            .layout(&head_config, &BTreeMap::new(), &LayoutCheck::Warn)
+            .unwrap();
+        let mail = WorkspaceName::new("mail");
+        assert_eq!(
+            layout.workspace_moves,
+            vec![(mail.clone(), "DP-1".to_string())]
+        );
+        assert_eq!(
+            layout.workspace_rules,
+            vec![(mail, vec!["DP-1".to_string()])]
+        );
+    }
+}
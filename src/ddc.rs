@@ -1,12 +1,24 @@
-use std::fmt::Debug;
+use std::{collections::BTreeMap, fmt::Debug};
 
 use libmonitor::mccs::features::InputSource;
+use libmonitor::{ddc::DdcDevice, Monitor};
 use serde::{
     de::{Error, Unexpected},
     Deserialize, Serialize,
 };
 use serde_yaml::Value;
 
+/// read the currently selected input of all monitors that support ddc
+pub fn current_monitor_inputs() -> BTreeMap<String, InputSource> {
+    let mut current_monitor_inputs = BTreeMap::new();
+    for mut monitor in Monitor::enumerate() {
+        if let Ok(monitor_input) = monitor.get_input_source() {
+            current_monitor_inputs.insert(monitor.handle.name(), monitor_input);
+        }
+    }
+    current_monitor_inputs
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum MonitorInputSourceMatcher {
     Any,
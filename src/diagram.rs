@@ -0,0 +1,210 @@
+use crate::layout::{self, LayoutViolation, PlacedScreen, Rect};
+
+/// screen to draw into the layout diagram
+pub struct DiagramScreen {
+    pub identifier: String,
+    pub rect: Rect,
+    pub labels: Vec<String>,
+    pub mirror: Option<String>,
+}
+
+// terminal cells are roughly twice as high as they are wide
+const CELL_ASPECT: f64 = 2.;
+
+struct Canvas {
+    cells: Vec<Vec<char>>,
+}
+
+impl Canvas {
+    fn new(columns: usize, rows: usize) -> Self {
+        Self {
+            cells: vec![vec![' '; columns]; rows],
+        }
+    }
+
+    fn set(&mut self, column: usize, row: usize, c: char) {
+        if let Some(cell) = self.cells.get_mut(row).and_then(|row| row.get_mut(column)) {
+            *cell = c;
+        }
+    }
+
+    fn fill(&mut self, area: (usize, usize, usize, usize), c: char) {
+        for row in area.1..=area.3 {
+            for column in area.0..=area.2 {
+                self.set(column, row, c);
+            }
+        }
+    }
+
+    fn draw_box(&mut self, area: (usize, usize, usize, usize), edge: (char, char)) {
+        let (c0, r0, c1, r1) = area;
+        for column in c0..=c1 {
+            self.set(column, r0, edge.0);
+            self.set(column, r1, edge.0);
+        }
+        for row in r0..=r1 {
+            self.set(c0, row, edge.1);
+            self.set(c1, row, edge.1);
+        }
+        for (column, row) in [(c0, r0), (c1, r0), (c0, r1), (c1, r1)] {
+            self.set(column, row, '+');
+        }
+    }
+
+    fn write(&mut self, column: usize, row: usize, text: &str, max_len: usize) {
+        for (offset, c) in text.chars().take(max_len).enumerate() {
+            self.set(column + offset, row, c);
+        }
+    }
+
+    fn into_string(self) -> String {
+        self.cells
+            .into_iter()
+            .map(|row| {
+                let mut line = row.into_iter().collect::<String>().trim_end().to_string();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
+}
+
+/// render a box diagram of the screens that fits into the given amount of terminal columns
+///
+/// Overlapping regions are filled with `#` and screens the cursor can not reach are drawn with `*` borders.
+pub fn render(screens: &[DiagramScreen], columns: usize) -> String {
+    if screens.is_empty() {
+        return "No screens to display!\n".to_string();
+    }
+    let columns = columns.max(20);
+    let min_x = screens.iter().map(|screen| screen.rect.x).min().unwrap_or(0);
+    let min_y = screens.iter().map(|screen| screen.rect.y).min().unwrap_or(0);
+    let max_x = screens.iter().map(|screen| screen.rect.right()).max().unwrap_or(0);
+    let max_y = screens.iter().map(|screen| screen.rect.bottom()).max().unwrap_or(0);
+
+    let pixels_per_column = ((max_x - min_x) as f64 / (columns - 1) as f64).max(1.);
+    let pixels_per_row = pixels_per_column * CELL_ASPECT;
+    let rows = ((max_y - min_y) as f64 / pixels_per_row).round() as usize + 1;
+
+    let to_cells = |rect: &Rect| {
+        let c0 = ((rect.x - min_x) as f64 / pixels_per_column).round() as usize;
+        let r0 = ((rect.y - min_y) as f64 / pixels_per_row).round() as usize;
+        let c1 = ((rect.right() - min_x) as f64 / pixels_per_column).round() as usize;
+        let r1 = ((rect.bottom() - min_y) as f64 / pixels_per_row).round() as usize;
+        (c0, r0, c1.max(c0 + 1), r1.max(r0 + 1))
+    };
+
+    let mut canvas = Canvas::new(columns, rows.max(2));
+
+    let violations = layout::validate(
+        &screens
+            .iter()
+            .map(|screen| PlacedScreen {
+                identifier: &screen.identifier,
+                rect: screen.rect,
+                mirror: screen.mirror.as_deref(),
+            })
+            .collect::<Vec<PlacedScreen>>(),
+    );
+
+    for screen in screens {
+        let unreachable = violations.contains(&LayoutViolation::Unreachable {
+            screen: screen.identifier.clone(),
+        });
+        let edge = if unreachable {
+            ('*', '*')
+        } else {
+            ('-', '|')
+        };
+        canvas.draw_box(to_cells(&screen.rect), edge);
+    }
+    for violation in &violations {
+        if let LayoutViolation::Overlap { area, .. } = violation {
+            canvas.fill(to_cells(area), '#');
+        }
+    }
+    for screen in screens {
+        let (c0, r0, c1, r1) = to_cells(&screen.rect);
+        for (line, label) in screen.labels.iter().enumerate() {
+            let row = r0 + 1 + line;
+            if row >= r1 {
+                break;
+            }
+            canvas.write(c0 + 1, row, label, c1.saturating_sub(c0 + 1));
+        }
+    }
+
+    let mut diagram = canvas.into_string();
+    for violation in violations {
+        diagram.push_str(&format!("! {violation}\n"));
+    }
+    diagram
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, DiagramScreen};
+    use crate::layout::Rect;
+
+    #[test]
+    fn render_layout_diagram() {
+        let diagram = render(
+            &[
+                DiagramScreen {
+                    identifier: "DP-1".to_string(),
+                    rect: Rect::new(0, 0, 1920, 1080),
+                    labels: vec!["DP-1".to_string(), "ws: 1 2".to_string()],
+                    mirror: None,
+                },
+                DiagramScreen {
+                    identifier: "DP-2".to_string(),
+                    rect: Rect::new(1920, 0, 1920, 1080),
+                    labels: vec!["DP-2".to_string()],
+                    mirror: None,
+                },
+            ],
+            41,
+        );
+        assert_eq!(
+            diagram,
+            "+-------------------+-------------------+\n\
+             |DP-1               |DP-2               |\n\
+             |ws: 1 2            |                   |\n\
+             |                   |                   |\n\
+             |                   |                   |\n\
+             |                   |                   |\n\
+             +-------------------+-------------------+\n"
+        );
+    }
+
+    #[test]
+    fn render_highlights_problems() {
+        let diagram = render(
+            &[
+                DiagramScreen {
+                    identifier: "DP-1".to_string(),
+                    rect: Rect::new(0, 0, 1920, 1080),
+                    labels: Vec::new(),
+                    mirror: None,
+                },
+                DiagramScreen {
+                    identifier: "DP-2".to_string(),
+                    rect: Rect::new(960, 0, 1920, 1080),
+                    labels: Vec::new(),
+                    mirror: None,
+                },
+                DiagramScreen {
+                    identifier: "DP-3".to_string(),
+                    rect: Rect::new(4000, 0, 1920, 1080),
+                    labels: Vec::new(),
+                    mirror: None,
+                },
+            ],
+            80,
+        );
+        assert!(diagram.contains('#'));
+        assert!(diagram.contains('*'));
+        assert!(diagram.contains("! DP-1 and DP-2 overlap by 960x1080 pixels at 960,0\n"));
+        assert!(diagram.contains("! DP-3 does not touch any other screen\n"));
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use wayland_client::protocol::wl_output::Transform;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedValue, Value},
+};
+
+use crate::{
+    configuration::SwayMonitor,
+    wlr_output_state::{
+        output_event_loop, pnp_vendor, read_pnp_ids, HeadConfiguration, MonitorInformation,
+        MonitorInformationBuilder, MonitorModeBuilder, OutputId,
+    },
+};
+
+type Properties = HashMap<String, OwnedValue>;
+/// connector, vendor, product and serial of a monitor
+type MonitorSpec = (String, String, String, String);
+/// id, width, height, refresh rate, preferred scale, supported scales and properties of a mode
+type MutterMode = (String, i32, i32, f64, f64, Vec<f64>, Properties);
+type MutterMonitor = (MonitorSpec, Vec<MutterMode>, Properties);
+/// x, y, scale, transform, primary, monitors and properties of a logical monitor
+type LogicalMonitor = (i32, i32, f64, u32, bool, Vec<MonitorSpec>, Properties);
+type CurrentState = (u32, Vec<MutterMonitor>, Vec<LogicalMonitor>, Properties);
+/// logical monitor as taken by `ApplyMonitorsConfig`, the monitors are given by connector and mode id
+type LogicalMonitorConfig<'a> = (
+    i32,
+    i32,
+    f64,
+    u32,
+    bool,
+    Vec<(String, String, HashMap<String, Value<'a>>)>,
+);
+
+// `ApplyMonitorsConfig` methods, verify only checks the configuration and temporary applies it
+// without storing it
+const VERIFY: u32 = 0;
+const TEMPORARY: u32 = 1;
+
+fn flag(properties: &Properties, name: &str) -> bool {
+    properties
+        .get(name)
+        .and_then(|value| value.downcast_ref::<bool>().ok())
+        .unwrap_or(false)
+}
+
+/// monitor information of the monitors in the state reported by mutter
+///
+/// Mutter reports the pnp id as vendor, it is turned into the make wlroots would report so that
+/// profiles match on every compositor.
+fn monitor_informations(
+    state: &CurrentState,
+    pnp_ids: &str,
+) -> anyhow::Result<HashMap<OutputId, MonitorInformation>> {
+    let (_, monitors, logical_monitors, _) = state;
+    let mut configuration = HashMap::new();
+    for ((connector, vendor, product, serial), modes, _) in monitors {
+        let logical_monitor = logical_monitors
+            .iter()
+            .find(|logical_monitor| logical_monitor.5.iter().any(|spec| spec.0 == *connector));
+        let make = pnp_vendor(vendor, pnp_ids);
+        let mut builder = MonitorInformationBuilder::default();
+        builder
+            .name(connector.clone())
+            .description(format!("{make} {product} {serial} ({connector})"))
+            .make(make)
+            .model(product.clone())
+            .serial(Some(serial.clone()))
+            .enabled(logical_monitor.is_some() as i32);
+        match logical_monitor {
+            Some((x, y, scale, transform, ..)) => builder
+                .position((*x, *y))
+                .scale(*scale)
+                .transform(Transform::try_from(*transform).unwrap_or(Transform::Normal)),
+            None => builder.scale(1.).transform(Transform::Normal),
+        };
+        for (_, width, height, refresh, _, _, properties) in modes {
+            builder.add_mode(
+                MonitorModeBuilder::default()
+                    .size((*width, *height))
+                    .refresh(refresh * 1000.)
+                    .preferred(flag(properties, "is-preferred"))
+                    .current(flag(properties, "is-current"))
+                    .build()?,
+            );
+        }
+        configuration.insert(OutputId::Name(connector.clone()), builder.build()?);
+    }
+    Ok(configuration)
+}
+
+/// logical monitors putting the layout into effect, mirrors join the logical monitor of their parent
+///
+/// Positions are taken as logical pixels, which matches mutter only in its logical layout mode.
+fn logical_monitor_configs<'a>(
+    state: &CurrentState,
+    monitors: &[SwayMonitor],
+) -> Vec<LogicalMonitorConfig<'a>> {
+    let (_, mutter_monitors, logical_monitors, _) = state;
+    let previous_primary = logical_monitors
+        .iter()
+        .find(|logical_monitor| logical_monitor.4)
+        .and_then(|logical_monitor| logical_monitor.5.first())
+        .map(|spec| spec.0.clone());
+    let mode_id = |monitor: &SwayMonitor| {
+        mutter_monitors
+            .iter()
+            .find(|(spec, _, _)| spec.0 == monitor.name)
+            .and_then(|(_, modes, _)| {
+                modes
+                    .iter()
+                    .filter(|mode| (mode.1, mode.2) == (monitor.width, monitor.height))
+                    .min_by(|a, b| {
+                        (a.3 - monitor.fps)
+                            .abs()
+                            .total_cmp(&(b.3 - monitor.fps).abs())
+                    })
+            })
+            .map(|mode| (mode.0.clone(), mode.5.clone()))
+    };
+    let parent = |monitor: &SwayMonitor| {
+        monitor.mirror.as_ref().and_then(|mirror| {
+            monitors
+                .iter()
+                .find(|other| other.identifier == *mirror && other.enabled)
+                .map(|other| other.name.clone())
+        })
+    };
+    let mut configs: Vec<(String, LogicalMonitorConfig)> = Vec::new();
+    // parents get their logical monitor first, so mirrors can join it whatever their order is
+    let (mirrors, parents): (Vec<&SwayMonitor>, Vec<&SwayMonitor>) = monitors
+        .iter()
+        .filter(|monitor| monitor.enabled)
+        .partition(|monitor| parent(monitor).is_some());
+    for monitor in parents.into_iter().chain(mirrors) {
+        let Some((mode_id, supported_scales)) = mode_id(monitor) else {
+            eprintln!(
+                "{} has no mode {}x{}",
+                monitor.name, monitor.width, monitor.height
+            );
+            continue;
+        };
+        let parent = parent(monitor);
+        let mirrored = configs
+            .iter_mut()
+            .find(|(name, _)| Some(name) == parent.as_ref());
+        let connector = (monitor.name.clone(), mode_id, HashMap::new());
+        match mirrored {
+            Some((_, config)) => config.5.push(connector),
+            None => {
+                // mutter only takes scales the mode supports
+                let scale = supported_scales
+                    .iter()
+                    .copied()
+                    .min_by(|a, b| {
+                        (a - monitor.scale)
+                            .abs()
+                            .total_cmp(&(b - monitor.scale).abs())
+                    })
+                    .unwrap_or(monitor.scale);
+                let transform: Transform = monitor.rotation.clone().into();
+                configs.push((
+                    monitor.name.clone(),
+                    (
+                        monitor.pos_x,
+                        monitor.pos_y,
+                        scale,
+                        u32::from(transform),
+                        previous_primary.as_ref() == Some(&monitor.name),
+                        vec![connector],
+                    ),
+                ))
+            }
+        }
+    }
+    // exactly one logical monitor has to be the primary one
+    if !configs.iter().any(|(_, config)| config.4) {
+        if let Some((_, config)) = configs.first_mut() {
+            config.4 = true;
+        }
+    }
+    configs.into_iter().map(|(_, config)| config).collect()
+}
+
+/// display configuration of mutter on the session bus
+struct DisplayConfig {
+    proxy: Proxy<'static>,
+}
+
+impl DisplayConfig {
+    fn connect() -> anyhow::Result<Self> {
+        Self::new(Connection::session()?)
+    }
+
+    fn new(connection: Connection) -> anyhow::Result<Self> {
+        Ok(Self {
+            proxy: Proxy::new_owned(
+                connection,
+                "org.gnome.Mutter.DisplayConfig",
+                "/org/gnome/Mutter/DisplayConfig",
+                "org.gnome.Mutter.DisplayConfig",
+            )?,
+        })
+    }
+
+    fn current_state(&self) -> anyhow::Result<CurrentState> {
+        Ok(self.proxy.call("GetCurrentState", &())?)
+    }
+
+    fn apply(&self, monitors: &[SwayMonitor], test: bool) -> anyhow::Result<()> {
+        let state = self.current_state()?;
+        let properties: HashMap<String, Value> = HashMap::new();
+        self.proxy.call::<_, _, ()>(
+            "ApplyMonitorsConfig",
+            &(
+                state.0,
+                if test { VERIFY } else { TEMPORARY },
+                logical_monitor_configs(&state, monitors),
+                properties,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+/// follow and configure the outputs over the display configuration d-bus api of mutter
+pub fn gnome_event_loop(
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+) -> anyhow::Result<()> {
+    let display_config = DisplayConfig::connect()?;
+    let pnp_ids = read_pnp_ids();
+    let events = display_config.proxy.receive_signal("MonitorsChanged")?;
+    let (event_tx, event_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for _ in events {
+            if event_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    output_event_loop(
+        wlr_tx,
+        config_head_rx,
+        &event_rx,
+        true,
+        || monitor_informations(&display_config.current_state()?, &pnp_ids),
+        |monitors, test| display_config.apply(monitors, test),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        logical_monitor_configs, monitor_informations, CurrentState, DisplayConfig, LogicalMonitor,
+        MutterMonitor, Properties,
+    };
+    use crate::{
+        configuration::{ScreenRotation, SwayMonitor},
+        wlr_output_state::OutputId,
+    };
+    use std::{
+        collections::HashMap,
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex},
+        thread,
+    };
+    use zbus::{
+        blocking::connection::Builder,
+        zvariant::{OwnedValue, Value},
+        Guid,
+    };
+
+    /// logical monitor config as received by mutter
+    type AppliedLogicalMonitor = (i32, i32, f64, u32, bool, Vec<(String, String, Properties)>);
+
+    /// display configuration of mutter answering with `mutter_state`
+    struct MockDisplayConfig {
+        applied: Arc<Mutex<Vec<(u32, usize)>>>,
+    }
+
+    #[zbus::interface(name = "org.gnome.Mutter.DisplayConfig")]
+    impl MockDisplayConfig {
+        fn get_current_state(&self) -> (u32, Vec<MutterMonitor>, Vec<LogicalMonitor>, Properties) {
+            mutter_state()
+        }
+
+        fn apply_monitors_config(
+            &self,
+            serial: u32,
+            method: u32,
+            logical_monitors: Vec<AppliedLogicalMonitor>,
+            _properties: Properties,
+        ) -> zbus::fdo::Result<()> {
+            if serial != mutter_state().0 {
+                return Err(zbus::fdo::Error::AccessDenied("stale serial".to_string()));
+            }
+            self.applied
+                .lock()
+                .unwrap()
+                .push((method, logical_monitors.len()));
+            Ok(())
+        }
+    }
+
+    fn flags(current: bool, preferred: bool) -> Properties {
+        HashMap::from([
+            ("is-current".to_string(), OwnedValue::from(current)),
+            ("is-preferred".to_string(), OwnedValue::from(preferred)),
+        ])
+    }
+
+    fn sway_monitor(identifier: &str, name: &str, mirror: Option<&str>) -> SwayMonitor {
+        SwayMonitor {
+            identifier: identifier.to_string(),
+            mirror: mirror.map(|mirror| mirror.to_string()),
+            enabled: true,
+            name: name.to_string(),
+            width: 2560,
+            height: 1440,
+            fps: 59.95,
+            pos_x: 1920,
+            pos_y: 0,
+            scale: 1.3,
+            rotation: ScreenRotation::Portrait,
+            workspaces: Vec::new(),
+        }
+    }
+
+    fn mutter_state() -> CurrentState {
+        let spec = |connector: &str| {
+            (
+                connector.to_string(),
+                "DEL".to_string(),
+                "DELL U2720Q".to_string(),
+                format!("{connector}-SERIAL"),
+            )
+        };
+        let mode = |id: &str, width, height, refresh, current, preferred| {
+            (
+                id.to_string(),
+                width,
+                height,
+                refresh,
+                1.,
+                vec![1., 1.25, 1.5],
+                flags(current, preferred),
+            )
+        };
+        (
+            7,
+            vec![
+                (
+                    spec("DP-1"),
+                    vec![
+                        mode("3840x2160@60", 3840, 2160, 60., false, true),
+                        mode("2560x1440@59.951", 2560, 1440, 59.951, true, false),
+                    ],
+                    HashMap::new(),
+                ),
+                (
+                    spec("HDMI-1"),
+                    vec![mode("2560x1440@60", 2560, 1440, 60., false, true)],
+                    HashMap::new(),
+                ),
+            ],
+            vec![(0, 0, 1., 1, true, vec![spec("DP-1")], HashMap::new())],
+            HashMap::new(),
+        )
+    }
+
+    #[test]
+    fn mutter_monitors() {
+        let state = mutter_state();
+        let monitors = monitor_informations(&state, "DEL\tDell Inc.\n").unwrap();
+        let dp = &monitors[&OutputId::Name("DP-1".to_string())];
+        assert_eq!(dp.identifier(), "Dell Inc. DP-1-SERIAL");
+        assert_eq!(*dp.preffered_mode().size(), (3840, 2160));
+        assert_eq!(*dp.active_mode().size(), (2560, 1440));
+        assert_eq!(
+            *monitors[&OutputId::Name("HDMI-1".to_string())].enabled(),
+            0
+        );
+
+        let configs = logical_monitor_configs(
+            &state,
+            &[
+                sway_monitor("DEL HDMI-1-SERIAL", "HDMI-1", None),
+                sway_monitor("DEL DP-1-SERIAL", "DP-1", Some("DEL HDMI-1-SERIAL")),
+            ],
+        );
+        assert_eq!(
+            configs,
+            vec![(
+                1920,
+                0,
+                1.25,
+                1,
+                true,
+                vec![
+                    (
+                        "HDMI-1".to_string(),
+                        "2560x1440@60".to_string(),
+                        HashMap::<String, Value>::new()
+                    ),
+                    (
+                        "DP-1".to_string(),
+                        "2560x1440@59.951".to_string(),
+                        HashMap::new()
+                    ),
+                ],
+            )]
+        );
+
+        // the mirror joins its parent even if it comes first
+        let configs = logical_monitor_configs(
+            &state,
+            &[
+                sway_monitor("DEL DP-1-SERIAL", "DP-1", Some("DEL HDMI-1-SERIAL")),
+                sway_monitor("DEL HDMI-1-SERIAL", "HDMI-1", None),
+            ],
+        );
+        assert_eq!(configs.len(), 1);
+        assert_eq!(
+            configs[0]
+                .5
+                .iter()
+                .map(|(connector, _, _)| connector.as_str())
+                .collect::<Vec<&str>>(),
+            ["HDMI-1", "DP-1"]
+        );
+    }
+
+    #[test]
+    fn mutter_display_config_over_dbus() {
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let (client, server) = UnixStream::pair().unwrap();
+        let mock = MockDisplayConfig {
+            applied: applied.clone(),
+        };
+        // both sides of the peer to peer connection have to authenticate at the same time
+        let server = thread::spawn(move || {
+            Builder::async_io_unix_stream(server)
+                .server(Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at("/org/gnome/Mutter/DisplayConfig", mock)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let display_config =
+            DisplayConfig::new(Builder::async_io_unix_stream(client).p2p().build().unwrap())
+                .unwrap();
+        let _server = server.join().unwrap();
+
+        let monitors = monitor_informations(&display_config.current_state().unwrap(), "").unwrap();
+        assert_eq!(
+            monitors[&OutputId::Name("DP-1".to_string())].identifier(),
+            "DEL DP-1-SERIAL"
+        );
+        let layout = [
+            sway_monitor("DEL HDMI-1-SERIAL", "HDMI-1", None),
+            sway_monitor("DEL DP-1-SERIAL", "DP-1", Some("DEL HDMI-1-SERIAL")),
+        ];
+        display_config.apply(&layout, true).unwrap();
+        display_config.apply(&layout, false).unwrap();
+        assert_eq!(*applied.lock().unwrap(), [(0, 1), (1, 1)]);
+    }
+}
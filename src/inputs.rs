@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::workspaces::quote;
+
+/// whether sway passes the events of an input device on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum InputEvents {
+    Enabled,
+    Disabled,
+    DisabledOnExternalMouse,
+}
+
+/// settings of a sway input device, unset values are left alone
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct InputSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub events: Option<InputEvents>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xkb_layout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accel: Option<f64>,
+    /// output the device is limited to, set through the `mapped_inputs` of the screens
+    #[serde(skip)]
+    pub map_to_output: Option<String>,
+}
+
+impl InputSettings {
+    fn is_empty(&self) -> bool {
+        self.events.is_none()
+            && self.xkb_layout.is_none()
+            && self.accel.is_none()
+            && self.map_to_output.is_none()
+    }
+
+    /// device covers the whole layout, which is what sway does for devices that are not mapped
+    fn unmapped() -> InputSettings {
+        InputSettings {
+            map_to_output: Some("*".to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// values of `self` for the settings that are set in `fields`
+    fn only(&self, fields: &InputSettings) -> InputSettings {
+        InputSettings {
+            events: self.events.filter(|_| fields.events.is_some()),
+            xkb_layout: self
+                .xkb_layout
+                .clone()
+                .filter(|_| fields.xkb_layout.is_some()),
+            accel: self.accel.filter(|_| fields.accel.is_some()),
+            map_to_output: self
+                .map_to_output
+                .clone()
+                .filter(|_| fields.map_to_output.is_some()),
+        }
+    }
+
+    /// values of `self` for the settings that are not set in `fields`
+    fn except(&self, fields: &InputSettings) -> InputSettings {
+        InputSettings {
+            events: self.events.filter(|_| fields.events.is_none()),
+            xkb_layout: self
+                .xkb_layout
+                .clone()
+                .filter(|_| fields.xkb_layout.is_none()),
+            accel: self.accel.filter(|_| fields.accel.is_none()),
+            map_to_output: self
+                .map_to_output
+                .clone()
+                .filter(|_| fields.map_to_output.is_none()),
+        }
+    }
+
+    /// values of `self`, missing ones are taken from `other`
+    fn or(self, other: InputSettings) -> InputSettings {
+        InputSettings {
+            events: self.events.or(other.events),
+            xkb_layout: self.xkb_layout.or(other.xkb_layout),
+            accel: self.accel.or(other.accel),
+            map_to_output: self.map_to_output.or(other.map_to_output),
+        }
+    }
+
+    /// sway commands configuring the inputs selected by an identifier, `type:<type>` or `*`
+    pub fn commands(&self, input: &str) -> Vec<String> {
+        let mut commands = Vec::new();
+        if let Some(events) = self.events {
+            let events = match events {
+                InputEvents::Enabled => "enabled",
+                InputEvents::Disabled => "disabled",
+                InputEvents::DisabledOnExternalMouse => "disabled_on_external_mouse",
+            };
+            commands.push(format!("input {} events {events}", quote(input)));
+        }
+        if let Some(xkb_layout) = &self.xkb_layout {
+            commands.push(format!(
+                "input {} xkb_layout {}",
+                quote(input),
+                quote(xkb_layout)
+            ));
+        }
+        if let Some(accel) = self.accel {
+            commands.push(format!("input {} pointer_accel {accel}", quote(input)));
+        }
+        if let Some(output) = &self.map_to_output {
+            commands.push(map_to_output_command(input, output));
+        }
+        commands
+    }
+}
+
+/// input device as reported by sway
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwayInput {
+    pub identifier: String,
+    pub input_type: String,
+    /// settings that can be read back from sway, the keyboard layout is only reported by description
+    pub current: InputSettings,
+}
+
+impl SwayInput {
+    fn selected_by(&self, selector: &str) -> bool {
+        selector == "*"
+            || selector == self.identifier
+            || selector.strip_prefix("type:") == Some(self.input_type.as_str())
+    }
+
+    /// combined settings of all selectors matching the device, more specific selectors take
+    /// precedence
+    fn settings_in(&self, inputs: &BTreeMap<String, InputSettings>) -> InputSettings {
+        by_precedence(inputs)
+            .filter(|(selector, _)| self.selected_by(selector))
+            .fold(InputSettings::default(), |settings, (_, selected)| {
+                selected.clone().or(settings)
+            })
+    }
+}
+
+/// input settings ordered from the least to the most specific selector: `*`, `type:<type>` and
+/// input identifiers, sway applies them in this order so the specific ones win
+fn by_precedence(
+    inputs: &BTreeMap<String, InputSettings>,
+) -> impl Iterator<Item = (&String, &InputSettings)> {
+    inputs.iter().sorted_by_key(|(selector, _)| {
+        if selector.as_str() == "*" {
+            0
+        } else if selector.starts_with("type:") {
+            1
+        } else {
+            2
+        }
+    })
+}
+
+// device types sway reports, a bare type is accepted instead of `type:<type>`
+const INPUT_TYPES: [&str; 7] = [
+    "keyboard",
+    "pointer",
+    "touchpad",
+    "touch",
+    "tablet_tool",
+    "tablet_pad",
+    "switch",
+];
+
+/// sway input selector for an input identifier, a device type or `type:<type>`
+pub fn selector(input: &str) -> String {
+    if INPUT_TYPES.contains(&input) {
+        format!("type:{input}")
+    } else {
+        input.to_string()
+    }
+}
+
+/// sway command limiting an input device to an output
+fn map_to_output_command(input: &str, output: &str) -> String {
+    format!("input {} map_to_output {}", quote(input), quote(output))
+}
+
+/// sway commands switching the input devices over to the settings of a profile
+///
+/// `restore` holds the values the devices had before any profile changed them. Settings the new
+/// profile does not touch anymore are restored, the values of newly changed settings are recorded.
+/// Sway only reports keyboard layouts by their description, so they are recorded from `defaults`
+/// which are used for every setting sway does not report. Mapped devices are restored to cover
+/// the whole layout.
+pub fn apply_commands(
+    restore: &mut BTreeMap<String, InputSettings>,
+    sway_inputs: &[SwayInput],
+    profile_inputs: &BTreeMap<String, InputSettings>,
+    defaults: &BTreeMap<String, InputSettings>,
+) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut new_restore = BTreeMap::new();
+    for sway_input in sway_inputs {
+        let target = sway_input.settings_in(profile_inputs);
+        let previous = restore.remove(&sway_input.identifier).unwrap_or_default();
+        let leftover = previous.except(&target);
+        if !leftover.is_empty() {
+            commands.extend(leftover.commands(&sway_input.identifier));
+        }
+        let recorded = previous
+            .only(&target)
+            .or(sway_input.current.only(&target))
+            .or(sway_input.settings_in(defaults).only(&target))
+            .or(InputSettings::unmapped().only(&target));
+        if !recorded.is_empty() {
+            new_restore.insert(sway_input.identifier.clone(), recorded);
+        }
+    }
+    for (selector, settings) in by_precedence(profile_inputs) {
+        commands.extend(settings.commands(selector));
+    }
+    *restore = new_restore;
+    commands
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        apply_commands, map_to_output_command, selector, InputEvents, InputSettings, SwayInput,
+    };
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn apply_and_restore_input_settings() {
+        let sway_inputs = [
+            SwayInput {
+                identifier: "1739:52710:Touchpad".to_string(),
+                input_type: "touchpad".to_string(),
+                current: InputSettings {
+                    events: Some(InputEvents::Enabled),
+                    xkb_layout: None,
+                    accel: Some(0.2),
+                    map_to_output: None,
+                },
+            },
+            SwayInput {
+                identifier: "1:1:Keyboard".to_string(),
+                input_type: "keyboard".to_string(),
+                current: InputSettings::default(),
+            },
+        ];
+        let docked: BTreeMap<String, InputSettings> = serde_yaml::from_str(
+            "type:touchpad: { events: Disabled, accel: 0.5 }\n\
+             1:1:Keyboard: { xkb_layout: de }\n",
+        )
+        .unwrap();
+        let defaults: BTreeMap<String, InputSettings> =
+            serde_yaml::from_str("type:keyboard: { xkb_layout: us }").unwrap();
+        let mut restore = BTreeMap::new();
+        assert_eq!(
+            apply_commands(&mut restore, &sway_inputs, &docked, &defaults),
+            [
+                "input \"type:touchpad\" events disabled",
+                "input \"type:touchpad\" pointer_accel 0.5",
+                "input \"1:1:Keyboard\" xkb_layout \"de\"",
+            ]
+        );
+
+        // the touchpad reports the values of the docked profile now
+        let mut sway_inputs = sway_inputs.to_vec();
+        sway_inputs[0].current = docked["type:touchpad"].clone();
+        let mobile: BTreeMap<String, InputSettings> =
+            serde_yaml::from_str("type:touchpad: { accel: 0.7 }").unwrap();
+        assert_eq!(
+            apply_commands(&mut restore, &sway_inputs, &mobile, &defaults),
+            [
+                "input \"1739:52710:Touchpad\" events enabled",
+                "input \"1:1:Keyboard\" xkb_layout \"us\"",
+                "input \"type:touchpad\" pointer_accel 0.7",
+            ]
+        );
+        assert_eq!(
+            restore["1739:52710:Touchpad"],
+            InputSettings {
+                events: None,
+                xkb_layout: None,
+                accel: Some(0.2),
+                map_to_output: None,
+            }
+        );
+    }
+
+    #[test]
+    fn identifier_settings_beat_type_settings() {
+        let sway_inputs = [SwayInput {
+            identifier: "1:1:Keyboard".to_string(),
+            input_type: "keyboard".to_string(),
+            current: InputSettings::default(),
+        }];
+        let profile: BTreeMap<String, InputSettings> = serde_yaml::from_str(
+            "type:keyboard: { xkb_layout: us }\n\
+             1:1:Keyboard: { xkb_layout: de }\n\
+             \"*\": { events: Enabled }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            sway_inputs[0].settings_in(&profile).xkb_layout.as_deref(),
+            Some("de")
+        );
+        assert_eq!(
+            apply_commands(
+                &mut BTreeMap::new(),
+                &sway_inputs,
+                &profile,
+                &BTreeMap::new()
+            ),
+            [
+                "input \"*\" events enabled",
+                "input \"type:keyboard\" xkb_layout \"us\"",
+                "input \"1:1:Keyboard\" xkb_layout \"de\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn map_inputs_to_outputs() {
+        assert_eq!(
+            map_to_output_command(&selector("tablet_tool"), "DP-1"),
+            "input \"type:tablet_tool\" map_to_output \"DP-1\""
+        );
+
+        // a mapping the next profile does not make anymore is undone
+        let sway_inputs = [SwayInput {
+            identifier: "1267:12377:ELAN_Touchscreen".to_string(),
+            input_type: "touch".to_string(),
+            current: InputSettings::default(),
+        }];
+        let mapped = BTreeMap::from([(
+            selector("touch"),
+            InputSettings {
+                map_to_output: Some("eDP-1".to_string()),
+                ..Default::default()
+            },
+        )]);
+        let mut restore = BTreeMap::new();
+        assert_eq!(
+            apply_commands(&mut restore, &sway_inputs, &mapped, &BTreeMap::new()),
+            ["input \"type:touch\" map_to_output \"eDP-1\""]
+        );
+        assert_eq!(
+            apply_commands(
+                &mut restore,
+                &sway_inputs,
+                &BTreeMap::new(),
+                &BTreeMap::new()
+            ),
+            ["input \"1267:12377:ELAN_Touchscreen\" map_to_output \"*\""]
+        );
+        assert!(restore.is_empty());
+        assert_eq!(selector("type:touch"), "type:touch");
+        assert_eq!(
+            selector("1267:12377:ELAN_Touchscreen"),
+            "1267:12377:ELAN_Touchscreen"
+        );
+    }
+}
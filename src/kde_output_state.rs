@@ -0,0 +1,432 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{Receiver, Sender},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+use wayland_client::{
+    backend::ObjectId,
+    event_created_child,
+    protocol::{wl_output::Transform, wl_registry},
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+use wayland_protocols_plasma::{
+    output_device::v2::client::{
+        kde_output_device_mode_v2::{self, KdeOutputDeviceModeV2},
+        kde_output_device_v2::{self, KdeOutputDeviceV2},
+    },
+    output_management::v2::client::{
+        kde_output_configuration_v2::{self, KdeOutputConfigurationV2},
+        kde_output_management_v2::KdeOutputManagementV2,
+    },
+};
+
+use crate::configuration::APPLY_TIMEOUT;
+use crate::wlr_output_state::{
+    closest_mode, HeadConfiguration, MonitorInformation, MonitorInformationBuilder, MonitorMode,
+    MonitorModeBuilder, OutputId,
+};
+
+/// output device announced by kwin, collects the events until the next `done`
+struct KdeDevice {
+    device: KdeOutputDeviceV2,
+    global_name: u32,
+    info: MonitorInformationBuilder,
+    modes: Vec<(KdeOutputDeviceModeV2, MonitorModeBuilder)>,
+    current_mode: Option<ObjectId>,
+}
+
+impl KdeDevice {
+    fn monitor_information(&self) -> Option<MonitorInformation> {
+        let mut info = self.info.clone();
+        for (mode, builder) in &self.modes {
+            let mut builder = builder.clone();
+            builder.current(self.current_mode == Some(mode.id()));
+            info.add_mode(builder.build().ok()?);
+        }
+        info.build()
+            .map_err(|err| eprintln!("Ignoring incomplete kde output device: {err}"))
+            .ok()
+    }
+}
+
+/// index into the device modes of the mode closest to the desired one, incomplete modes are skipped
+fn device_mode<'a>(
+    modes: impl Iterator<Item = &'a MonitorModeBuilder>,
+    size: (i32, i32),
+    fps: f64,
+) -> Option<usize> {
+    let (indexes, modes): (Vec<usize>, Vec<MonitorMode>) = modes
+        .enumerate()
+        .filter_map(|(index, builder)| Some((index, builder.build().ok()?)))
+        .unzip();
+    closest_mode(&modes, size, fps).map(|index| indexes[index])
+}
+
+struct KdeOutputState {
+    output_management: Option<KdeOutputManagementV2>,
+    wlr_tx: Sender<HashMap<OutputId, MonitorInformation>>,
+    devices: HashMap<ObjectId, KdeDevice>,
+    // device each mode belongs to
+    mode_devices: HashMap<ObjectId, ObjectId>,
+    current_configuration: HashMap<OutputId, MonitorInformation>,
+    // answer channel of the configuration kwin did not answer yet
+    pending_result: Option<PendingResult>,
+}
+
+/// configuration sent to kwin that did not get an answer yet
+struct PendingResult {
+    // configuration object kwin answers on
+    id: ObjectId,
+    result_tx: Sender<anyhow::Result<()>>,
+    // the daemon stops waiting for the answer at this point
+    deadline: Instant,
+}
+
+impl KdeOutputState {
+    fn new(wlr_tx: Sender<HashMap<OutputId, MonitorInformation>>) -> Self {
+        Self {
+            output_management: None,
+            wlr_tx,
+            devices: HashMap::new(),
+            mode_devices: HashMap::new(),
+            current_configuration: HashMap::new(),
+            pending_result: None,
+        }
+    }
+
+    fn update_head_configuration(
+        &mut self,
+        configuration: HeadConfiguration,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let Some(ref output_management) = self.output_management {
+            let output_configuration = output_management.create_configuration(qh, ());
+            for (id, desired_config) in configuration.monitors {
+                let OutputId::Kde(id) = id else { continue };
+                if let Some(device) = self.devices.get(&id) {
+                    println!("updating monitor: {desired_config:#?}");
+                    if desired_config.enabled {
+                        output_configuration.enable(&device.device, 1);
+                        if let Some(index) = device_mode(
+                            device.modes.iter().map(|(_, builder)| builder),
+                            (desired_config.width, desired_config.height),
+                            desired_config.fps,
+                        ) {
+                            output_configuration.mode(&device.device, &device.modes[index].0);
+                        }
+                        let transform: Transform = desired_config.rotation.clone().into();
+                        output_configuration.transform(&device.device, u32::from(transform) as i32);
+                        output_configuration.position(
+                            &device.device,
+                            desired_config.pos_x,
+                            desired_config.pos_y,
+                        );
+                        output_configuration.scale(&device.device, desired_config.scale);
+                    } else {
+                        output_configuration.enable(&device.device, 0);
+                    }
+                }
+            }
+            output_configuration.apply();
+            self.pending_result = Some(PendingResult {
+                id: output_configuration.id(),
+                result_tx: configuration.result_tx,
+                deadline: Instant::now() + Duration::from_millis(APPLY_TIMEOUT),
+            });
+        }
+    }
+
+    /// give up a configuration kwin did not answer in time, so the next one can be sent
+    fn expire_pending_result(&mut self) {
+        if let Some(pending) = self
+            .pending_result
+            .take_if(|pending| Instant::now() >= pending.deadline)
+        {
+            let err = anyhow::anyhow!("KWin did not answer the output configuration");
+            eprintln!("{err:#}");
+            let _ = pending.result_tx.send(Err(err));
+        }
+    }
+
+    fn send_configuration(&self) {
+        let _ = self.wlr_tx.send(self.current_configuration.clone());
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for KdeOutputState {
+    fn event(
+        state: &mut Self,
+        proxy: &wl_registry::WlRegistry,
+        event: <wl_registry::WlRegistry as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => {
+                if interface == KdeOutputManagementV2::interface().name {
+                    state.output_management = Some(proxy.bind(name, version.min(1), qh, ()));
+                } else if interface == KdeOutputDeviceV2::interface().name {
+                    // version 2 adds the output name
+                    let device: KdeOutputDeviceV2 = proxy.bind(name, version.min(2), qh, ());
+                    let mut info = MonitorInformationBuilder::default();
+                    info.scale(1.);
+                    state.devices.insert(
+                        device.id(),
+                        KdeDevice {
+                            device,
+                            global_name: name,
+                            info,
+                            modes: Vec::new(),
+                            current_mode: None,
+                        },
+                    );
+                }
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                let removed = state
+                    .devices
+                    .iter()
+                    .find(|(_, device)| device.global_name == name)
+                    .map(|(id, _)| id.clone());
+                if let Some(id) = removed {
+                    state.devices.remove(&id);
+                    state.mode_devices.retain(|_, device| *device != id);
+                    state.current_configuration.remove(&OutputId::Kde(id));
+                    state.send_configuration();
+                }
+            }
+            _ => { /* Nothing to do here */ }
+        }
+    }
+}
+
+impl Dispatch<KdeOutputDeviceV2, ()> for KdeOutputState {
+    fn event(
+        state: &mut Self,
+        proxy: &KdeOutputDeviceV2,
+        event: <KdeOutputDeviceV2 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        if let kde_output_device_v2::Event::Done = event {
+            let monitor_information = state
+                .devices
+                .get(&proxy.id())
+                .and_then(|device| device.monitor_information());
+            if let Some(monitor_information) = monitor_information {
+                // only send configuration for devices that were not known before
+                let id = OutputId::Kde(proxy.id());
+                let is_new = !state.current_configuration.contains_key(&id);
+                state.current_configuration.insert(id, monitor_information);
+                if is_new {
+                    state.send_configuration();
+                }
+            }
+            return;
+        }
+        if let kde_output_device_v2::Event::Mode { mode } = &event {
+            state.mode_devices.insert(mode.id(), proxy.id());
+        }
+        let Some(device) = state.devices.get_mut(&proxy.id()) else {
+            return;
+        };
+        match event {
+            kde_output_device_v2::Event::Geometry {
+                x,
+                y,
+                physical_width,
+                physical_height,
+                make,
+                model,
+                transform,
+                ..
+            } => {
+                device
+                    .info
+                    .position((x, y))
+                    .size((physical_width, physical_height))
+                    .description(format!("{make} {model}"))
+                    .make(make)
+                    .model(model)
+                    .transform(Transform::try_from(transform as u32).unwrap_or(Transform::Normal));
+            }
+            kde_output_device_v2::Event::CurrentMode { mode } => {
+                device.current_mode = Some(mode.id());
+            }
+            kde_output_device_v2::Event::Mode { mode } => {
+                device.modes.push((mode, MonitorModeBuilder::default()));
+            }
+            kde_output_device_v2::Event::Scale { factor } => {
+                device.info.scale(factor);
+            }
+            kde_output_device_v2::Event::Enabled { enabled } => {
+                device.info.enabled(enabled);
+            }
+            kde_output_device_v2::Event::SerialNumber { serialNumber } => {
+                device.info.serial(serialNumber);
+            }
+            kde_output_device_v2::Event::Name { name } => {
+                device.info.name(name);
+            }
+            _ => { /* Nothing to do here */ }
+        }
+    }
+
+    event_created_child!(KdeOutputState, KdeOutputDeviceV2, [
+        kde_output_device_v2::EVT_MODE_OPCODE => (KdeOutputDeviceModeV2, ())
+    ]);
+}
+
+impl Dispatch<KdeOutputDeviceModeV2, ()> for KdeOutputState {
+    fn event(
+        state: &mut Self,
+        proxy: &KdeOutputDeviceModeV2,
+        event: <KdeOutputDeviceModeV2 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let Some(device) = state
+            .mode_devices
+            .get(&proxy.id())
+            .and_then(|device| state.devices.get_mut(device))
+        else {
+            return;
+        };
+        let Some(index) = device.modes.iter().position(|(mode, _)| mode == proxy) else {
+            return;
+        };
+        match event {
+            kde_output_device_mode_v2::Event::Size { width, height } => {
+                device.modes[index].1.size((width, height));
+            }
+            kde_output_device_mode_v2::Event::Refresh { refresh } => {
+                device.modes[index].1.refresh(refresh);
+            }
+            kde_output_device_mode_v2::Event::Preferred => {
+                device.modes[index].1.preferred(true);
+            }
+            kde_output_device_mode_v2::Event::Removed => {
+                device.modes.remove(index);
+                state.mode_devices.remove(&proxy.id());
+            }
+            _ => { /* Nothing to do here */ }
+        }
+    }
+}
+
+impl Dispatch<KdeOutputManagementV2, ()> for KdeOutputState {
+    fn event(
+        _state: &mut Self,
+        _proxy: &KdeOutputManagementV2,
+        _event: <KdeOutputManagementV2 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        /* the output management has no events */
+    }
+}
+
+impl Dispatch<KdeOutputConfigurationV2, ()> for KdeOutputState {
+    fn event(
+        state: &mut Self,
+        proxy: &KdeOutputConfigurationV2,
+        event: <KdeOutputConfigurationV2 as Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qhandle: &QueueHandle<Self>,
+    ) {
+        let result = match event {
+            kde_output_configuration_v2::Event::Applied => Ok(()),
+            kde_output_configuration_v2::Event::Failed => {
+                Err(anyhow::anyhow!("KWin refused the output configuration"))
+            }
+            _ => return,
+        };
+        let id = proxy.id();
+        proxy.destroy();
+        // late answers to configurations that were given up are dropped
+        if let Some(pending) = state.pending_result.take_if(|pending| pending.id == id) {
+            let _ = pending.result_tx.send(result);
+        }
+    }
+}
+
+/// follow and configure the outputs over the kde output management protocol of kwin
+pub fn kde_event_loop(
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+) -> anyhow::Result<()> {
+    let conn = Connection::connect_to_env()?;
+
+    let display = conn.display();
+
+    let mut kde_events = conn.new_event_queue();
+    let qh = kde_events.handle();
+
+    let _registry = display.get_registry(&qh, ());
+
+    let mut state = KdeOutputState::new(wlr_tx.clone());
+    kde_events.roundtrip(&mut state)?;
+    if state.output_management.is_none() {
+        bail!("the compositor does not support kde output management");
+    }
+
+    loop {
+        let x = kde_events.roundtrip(&mut state);
+        state.expire_pending_result();
+
+        let mut update_event_happend = false;
+        // one configuration at a time
+        if state.pending_result.is_none() {
+            if let Some(update_head_event) = config_head_rx
+                .try_recv()
+                .ok()
+                .and_then(HeadConfiguration::applicable)
+            {
+                update_event_happend = true;
+                state.update_head_configuration(update_head_event, &qh);
+            }
+        }
+        // if nothing happend in this loop iteration sleep for a while to save power
+        if !update_event_happend && x.is_ok_and(|num_events| num_events == 0) {
+            sleep(std::time::Duration::from_millis(100));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::device_mode;
+    use crate::wlr_output_state::MonitorModeBuilder;
+
+    #[test]
+    fn device_mode_skips_incomplete_modes() {
+        let mode = |size: (i32, i32), refresh: i32| {
+            let mut builder = MonitorModeBuilder::default();
+            builder.size(size).refresh(refresh);
+            builder
+        };
+        let mut incomplete = MonitorModeBuilder::default();
+        incomplete.refresh(60000);
+        let modes = [
+            incomplete,
+            mode((1920, 1080), 60000),
+            mode((1920, 1080), 144000),
+            mode((2560, 1440), 59951),
+        ];
+        assert_eq!(device_mode(modes.iter(), (1920, 1080), 143.9), Some(2));
+        assert_eq!(device_mode(modes.iter(), (2560, 1440), 60.), Some(3));
+        assert_eq!(device_mode(modes.iter(), (1280, 720), 60.), None);
+    }
+}
@@ -0,0 +1,1048 @@
+use std::fmt::Display;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::ScreenPositionRelative;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// area of an output at `position` with a mode of `size` pixels shown at `scale`
+    pub fn logical(position: (i32, i32), size: (i32, i32), scale: f64) -> Self {
+        let scale = if scale > 0. { scale } else { 1. };
+        Rect::new(
+            position.0,
+            position.1,
+            (size.0 as f64 / scale).round() as i32,
+            (size.1 as f64 / scale).round() as i32,
+        )
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + self.width / 2, self.y + self.height / 2)
+    }
+
+    pub fn contains(&self, point: (i32, i32)) -> bool {
+        point.0 >= self.x && point.0 < self.right() && point.1 >= self.y && point.1 < self.bottom()
+    }
+
+    /// area covered by both rectangles, empty rectangles never intersect
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        if self.overlaps_horizontally(other) && self.overlaps_vertically(other) {
+            let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+            Some(Rect::new(
+                x,
+                y,
+                self.right().min(other.right()) - x,
+                self.bottom().min(other.bottom()) - y,
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// check if the cursor can travel between both rectangles, they need to share a part of an edge or overlap
+    pub fn touches(&self, other: &Rect) -> bool {
+        ((self.right() == other.x || other.right() == self.x) && self.overlaps_vertically(other))
+            || ((self.bottom() == other.y || other.bottom() == self.y)
+                && self.overlaps_horizontally(other))
+            || self.intersection(other).is_some()
+    }
+
+    /// check if two rectangles share a horizontal range of pixels
+    fn overlaps_horizontally(&self, other: &Rect) -> bool {
+        self.x < other.right() && other.x < self.right()
+    }
+
+    /// check if two rectangles share a vertical range of pixels
+    fn overlaps_vertically(&self, other: &Rect) -> bool {
+        self.y < other.bottom() && other.y < self.bottom()
+    }
+}
+
+/// constraint for the position of a screen relative to other screens of the profile
+///
+/// Constraints referring to screens that are not connected are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum PositionConstraint {
+    /// place the top left corner at the origin of the layout
+    Origin,
+    /// left edge touches the right edge of the other screen
+    RightOf(String),
+    /// right edge touches the left edge of the other screen
+    LeftOf(String),
+    /// bottom edge touches the top edge of the other screen
+    Above(String),
+    /// top edge touches the bottom edge of the other screen
+    Below(String),
+    AlignTop(String),
+    AlignBottom(String),
+    AlignLeft(String),
+    AlignRight(String),
+    /// horizontal centers of both screens are aligned
+    CenterHorizontally(String),
+    /// vertical centers of both screens are aligned
+    CenterVertically(String),
+    /// horizontally centered in the gap between the right edge of the first and the left edge of the second screen
+    CenteredBetween(String, String),
+    /// same position as the other screen
+    SameAs(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+impl Display for Axis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Axis::X => write!(f, "x"),
+            Axis::Y => write!(f, "y"),
+        }
+    }
+}
+
+/// equation for the coordinate of a screen on one axis
+enum Term<'a> {
+    Zero,
+    After(&'a str),
+    Before(&'a str),
+    Start(&'a str),
+    End(&'a str),
+    Center(&'a str),
+    Between(&'a str, &'a str),
+}
+
+impl Term<'_> {
+    fn references(&self) -> Vec<&str> {
+        match self {
+            Term::Zero => Vec::new(),
+            Term::After(other)
+            | Term::Before(other)
+            | Term::Start(other)
+            | Term::End(other)
+            | Term::Center(other) => vec![other],
+            Term::Between(first, second) => vec![first, second],
+        }
+    }
+
+    /// linear equation `coefficients * coordinates = constant` for the coordinate of the screen at `own`
+    ///
+    /// `sizes` contains the size of every screen on the same axis and `index_of` resolves identifiers.
+    fn equation(
+        &self,
+        own: usize,
+        sizes: &[i32],
+        index_of: impl Fn(&str) -> usize,
+    ) -> (Vec<f64>, f64) {
+        let mut coefficients = vec![0.; sizes.len()];
+        coefficients[own] += 1.;
+        let own_size = sizes[own] as f64;
+        let mut reference = |other: &str, factor: f64| {
+            let other = index_of(other);
+            coefficients[other] -= factor;
+            sizes[other] as f64
+        };
+        let constant = match self {
+            Term::Zero => 0.,
+            Term::After(other) => reference(other, 1.),
+            Term::Before(other) => {
+                reference(other, 1.);
+                -own_size
+            }
+            Term::Start(other) => {
+                reference(other, 1.);
+                0.
+            }
+            Term::End(other) => reference(other, 1.) - own_size,
+            Term::Center(other) => (reference(other, 1.) - own_size) / 2.,
+            Term::Between(first, second) => {
+                let first_size = reference(first, 0.5);
+                reference(second, 0.5);
+                (first_size - own_size) / 2.
+            }
+        };
+        (coefficients, constant)
+    }
+}
+
+impl PositionConstraint {
+    fn terms(&self) -> Vec<(Axis, Term<'_>)> {
+        match self {
+            PositionConstraint::Origin => vec![(Axis::X, Term::Zero), (Axis::Y, Term::Zero)],
+            PositionConstraint::RightOf(other) => vec![(Axis::X, Term::After(other))],
+            PositionConstraint::LeftOf(other) => vec![(Axis::X, Term::Before(other))],
+            PositionConstraint::Above(other) => vec![(Axis::Y, Term::Before(other))],
+            PositionConstraint::Below(other) => vec![(Axis::Y, Term::After(other))],
+            PositionConstraint::AlignTop(other) => vec![(Axis::Y, Term::Start(other))],
+            PositionConstraint::AlignBottom(other) => vec![(Axis::Y, Term::End(other))],
+            PositionConstraint::AlignLeft(other) => vec![(Axis::X, Term::Start(other))],
+            PositionConstraint::AlignRight(other) => vec![(Axis::X, Term::End(other))],
+            PositionConstraint::CenterHorizontally(other) => vec![(Axis::X, Term::Center(other))],
+            PositionConstraint::CenterVertically(other) => vec![(Axis::Y, Term::Center(other))],
+            PositionConstraint::CenteredBetween(first, second) => {
+                vec![(Axis::X, Term::Between(first, second))]
+            }
+            PositionConstraint::SameAs(other) => {
+                vec![(Axis::X, Term::Start(other)), (Axis::Y, Term::Start(other))]
+            }
+        }
+    }
+
+    /// identifier of the screen that is mirrored by this constraint
+    pub fn mirrored(&self) -> Option<&str> {
+        match self {
+            PositionConstraint::SameAs(other) => Some(other),
+            _ => None,
+        }
+    }
+}
+
+impl ScreenPositionRelative {
+    /// express the relative position as constraints for the layout solver
+    pub fn constraints(&self) -> Vec<PositionConstraint> {
+        use PositionConstraint::*;
+        match self.clone() {
+            ScreenPositionRelative::Root => vec![Origin],
+            ScreenPositionRelative::Over(parent) => vec![Above(parent.clone()), AlignLeft(parent)],
+            ScreenPositionRelative::Under(parent) => vec![Below(parent.clone()), AlignLeft(parent)],
+            ScreenPositionRelative::Left(parent) => vec![LeftOf(parent.clone()), AlignTop(parent)],
+            ScreenPositionRelative::Right(parent) => {
+                vec![RightOf(parent.clone()), AlignTop(parent)]
+            }
+            ScreenPositionRelative::LeftOver(parent) => vec![LeftOf(parent.clone()), Above(parent)],
+            ScreenPositionRelative::LeftUnder(parent) => {
+                vec![LeftOf(parent.clone()), Below(parent)]
+            }
+            ScreenPositionRelative::RightOver(parent) => {
+                vec![RightOf(parent.clone()), Above(parent)]
+            }
+            ScreenPositionRelative::RightUnder(parent) => {
+                vec![RightOf(parent.clone()), Below(parent)]
+            }
+            ScreenPositionRelative::Mirror(parent) => vec![SameAs(parent)],
+        }
+    }
+}
+
+/// screen that should be placed by the layout solver, disabled screens have a size of zero
+pub struct SolverScreen<'a> {
+    pub identifier: &'a str,
+    pub size: (i32, i32),
+    pub constraints: Vec<PositionConstraint>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    /// constraints of a screen require different coordinates on the same axis
+    Contradiction {
+        screen: String,
+        constraint: PositionConstraint,
+        axis: Axis,
+        required: i32,
+        current: i32,
+    },
+    /// the screen has neither a relative position nor any constraints
+    Unconstrained { screen: String },
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::Contradiction {
+                screen,
+                constraint,
+                axis,
+                required,
+                current,
+            } => write!(
+                f,
+                "constraint {constraint:?} of {screen} requires {axis} = {required} but other constraints already place it at {axis} = {current}"
+            ),
+            LayoutError::Unconstrained { screen } => {
+                write!(f, "{screen} has neither a position nor any constraints")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+// tolerance for coordinates to be considered equal, everything below one pixel is rounded away anyway
+const EPSILON: f64 = 1e-9;
+const PIXEL_TOLERANCE: f64 = 0.5;
+
+/// solve a system of linear equations with gaussian elimination
+///
+/// Unknowns that are not determined by the equations are set to zero. Returns `None` if the
+/// equations contradict each other.
+fn solve_linear(equations: &[(Vec<f64>, f64)], unknowns: usize) -> Option<Vec<f64>> {
+    let mut rows = equations.to_vec();
+    let mut pivots = Vec::new();
+    for column in 0..unknowns {
+        let rank = pivots.len();
+        let Some(pivot) = (rank..rows.len())
+            .filter(|row| rows[*row].0[column].abs() > EPSILON)
+            .max_by(|a, b| {
+                rows[*a].0[column]
+                    .abs()
+                    .total_cmp(&rows[*b].0[column].abs())
+            })
+        else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+        let factor = rows[rank].0[column];
+        rows[rank]
+            .0
+            .iter_mut()
+            .for_each(|coefficient| *coefficient /= factor);
+        rows[rank].1 /= factor;
+        for row in 0..rows.len() {
+            let factor = rows[row].0[column];
+            if row != rank && factor.abs() > EPSILON {
+                for c in 0..unknowns {
+                    rows[row].0[c] -= factor * rows[rank].0[c];
+                }
+                rows[row].1 -= factor * rows[rank].1;
+            }
+        }
+        pivots.push(column);
+    }
+    // equations without a pivot reduced to `0 = constant`
+    if rows[pivots.len()..]
+        .iter()
+        .any(|(_, constant)| constant.abs() > PIXEL_TOLERANCE)
+    {
+        return None;
+    }
+    let mut solution = vec![0.; unknowns];
+    for (row, column) in pivots.into_iter().enumerate() {
+        solution[column] = rows[row].1;
+    }
+    Some(solution)
+}
+
+/// compute the positions of all screens so that every constraint is satisfied
+///
+/// Both axes are solved independently as a system of linear equations, so constraints may refer to
+/// each other in any order. Coordinates that are not determined by any constraint are set to zero,
+/// which is where screens without a usable constraint (e.g. all referenced screens are missing) end up.
+/// Screens without any constraint at all are reported as an error instead.
+pub fn solve(screens: &[SolverScreen]) -> Result<Vec<(i32, i32)>, LayoutError> {
+    if let Some(screen) = screens.iter().find(|screen| screen.constraints.is_empty()) {
+        return Err(LayoutError::Unconstrained {
+            screen: screen.identifier.to_string(),
+        });
+    }
+
+    let index_of = |identifier: &str| {
+        screens
+            .iter()
+            .position(|screen| screen.identifier == identifier)
+    };
+
+    let mut solutions = Vec::new();
+    for axis in [Axis::X, Axis::Y] {
+        let sizes = screens
+            .iter()
+            .map(|screen| match axis {
+                Axis::X => screen.size.0,
+                Axis::Y => screen.size.1,
+            })
+            .collect::<Vec<i32>>();
+
+        let mut equations = Vec::new();
+        let mut solution = vec![0.; screens.len()];
+        for (index, screen) in screens.iter().enumerate() {
+            for constraint in &screen.constraints {
+                for (term_axis, term) in constraint.terms() {
+                    // constraints referring to screens that are not attached are ignored
+                    if term_axis != axis
+                        || term
+                            .references()
+                            .iter()
+                            .any(|other| index_of(other).is_none())
+                    {
+                        continue;
+                    }
+                    let equation =
+                        term.equation(index, &sizes, |other| index_of(other).unwrap_or(index));
+                    equations.push(equation.clone());
+                    match solve_linear(&equations, screens.len()) {
+                        Some(new_solution) => solution = new_solution,
+                        None => {
+                            // report where the other constraints placed the screen and where this one wants it to be
+                            let current = solution[index];
+                            let required = equation.1
+                                - equation
+                                    .0
+                                    .iter()
+                                    .enumerate()
+                                    .filter(|(other, _)| *other != index)
+                                    .map(|(other, coefficient)| coefficient * solution[other])
+                                    .sum::<f64>();
+                            return Err(LayoutError::Contradiction {
+                                screen: screen.identifier.to_string(),
+                                constraint: constraint.clone(),
+                                axis,
+                                required: required.round() as i32,
+                                current: current.round() as i32,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        solutions.push(solution);
+    }
+
+    Ok((0..screens.len())
+        .map(|index| {
+            (
+                solutions[0][index].round() as i32,
+                solutions[1][index].round() as i32,
+            )
+        })
+        .collect())
+}
+
+/// how to handle layouts that fail the sanity checks before they are applied
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub enum LayoutCheck {
+    /// only report the problems and apply the layout anyway
+    #[default]
+    Warn,
+    /// do not apply layouts with problems
+    Refuse,
+    /// move screens until the layout does not have any problems
+    Repair,
+}
+
+/// screen placed in a computed layout, `mirror` is the identifier of the screen it mirrors
+#[derive(Debug, Clone)]
+pub struct PlacedScreen<'a> {
+    pub identifier: &'a str,
+    pub rect: Rect,
+    pub mirror: Option<&'a str>,
+}
+
+impl PlacedScreen<'_> {
+    fn mirrors(&self, other: &PlacedScreen) -> bool {
+        self.mirror == Some(other.identifier) || other.mirror == Some(self.identifier)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutViolation {
+    /// two screens that do not mirror each other share some pixels
+    Overlap {
+        screen: String,
+        other: String,
+        area: Rect,
+    },
+    /// the cursor can not travel from the rest of the layout to this screen
+    Unreachable { screen: String },
+}
+
+impl Display for LayoutViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutViolation::Overlap {
+                screen,
+                other,
+                area,
+            } => write!(
+                f,
+                "{screen} and {other} overlap by {}x{} pixels at {},{}",
+                area.width, area.height, area.x, area.y
+            ),
+            LayoutViolation::Unreachable { screen } => {
+                write!(f, "{screen} does not touch any other screen")
+            }
+        }
+    }
+}
+
+/// check that screens do not overlap (except mirrors) and that the cursor can reach every screen
+pub fn validate(screens: &[PlacedScreen]) -> Vec<LayoutViolation> {
+    let mut violations = Vec::new();
+    for (index, screen) in screens.iter().enumerate() {
+        for other in &screens[index + 1..] {
+            if screen.mirrors(other) {
+                continue;
+            }
+            if let Some(area) = screen.rect.intersection(&other.rect) {
+                violations.push(LayoutViolation::Overlap {
+                    screen: screen.identifier.to_string(),
+                    other: other.identifier.to_string(),
+                    area,
+                });
+            }
+        }
+    }
+    for index in disconnected(
+        &screens
+            .iter()
+            .map(|screen| screen.rect)
+            .collect::<Vec<Rect>>(),
+    ) {
+        violations.push(LayoutViolation::Unreachable {
+            screen: screens[index].identifier.to_string(),
+        });
+    }
+    violations
+}
+
+/// move screens that overlap others or can not be reached next to the screens that are fine
+///
+/// Screens are visited from the top left to the bottom right, a screen keeps its position if it
+/// touches the screens visited before without overlapping them. Otherwise it is placed to the right
+/// of the one reaching furthest right, aligned with its top edge. Mirrors follow the screen they
+/// mirror. Returns the new position of every screen.
+pub fn repair(screens: &[PlacedScreen]) -> Vec<(i32, i32)> {
+    let mut positions = screens
+        .iter()
+        .map(|screen| (screen.rect.x, screen.rect.y))
+        .collect::<Vec<(i32, i32)>>();
+    let order = (0..screens.len())
+        .filter(|index| screens[*index].mirror.is_none())
+        .sorted_by_key(|index| (screens[*index].rect.y, screens[*index].rect.x))
+        .collect::<Vec<usize>>();
+
+    let mut placed: Vec<Rect> = Vec::new();
+    for index in order {
+        let rect = screens[index].rect;
+        let fits = placed.is_empty()
+            || (placed.iter().any(|other| rect.touches(other))
+                && placed
+                    .iter()
+                    .all(|other| rect.intersection(other).is_none()));
+        // the screen reaching furthest right keeps the moved screen reachable
+        let rect = match placed.iter().max_by_key(|other| other.right()) {
+            Some(anchor) if !fits => Rect::new(anchor.right(), anchor.y, rect.width, rect.height),
+            _ => rect,
+        };
+        positions[index] = (rect.x, rect.y);
+        placed.push(rect);
+    }
+
+    // mirrors are placed at the position of the screen they mirror
+    for (index, screen) in screens.iter().enumerate() {
+        if let Some(parent) = screen.mirror {
+            if let Some(parent_index) = screens
+                .iter()
+                .position(|other| other.identifier == parent && other.mirror.is_none())
+            {
+                positions[index] = positions[parent_index];
+            }
+        }
+    }
+    positions
+}
+
+/// indices of all rectangles that can not be reached from the first rectangle by moving the cursor
+pub fn disconnected(rects: &[Rect]) -> Vec<usize> {
+    if rects.is_empty() {
+        return Vec::new();
+    }
+    let mut reached = vec![false; rects.len()];
+    reached[0] = true;
+    let mut queue = vec![0];
+    while let Some(current) = queue.pop() {
+        for (index, rect) in rects.iter().enumerate() {
+            if !reached[index] && rects[current].touches(rect) {
+                reached[index] = true;
+                queue.push(index);
+            }
+        }
+    }
+    reached
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, reached)| (!reached).then_some(index))
+        .collect()
+}
+
+/// find the relative position of `screen` in relation to the `parent` screen if they touch each other
+fn relation_to(parent: &Rect, screen: &Rect, parent_ident: &str) -> Option<ScreenPositionRelative> {
+    let parent_ident = parent_ident.to_string();
+    // exact matches first, they reproduce the same coordinates when the profile gets applied
+    let relation = if screen.x == parent.x && screen.y == parent.y {
+        ScreenPositionRelative::Mirror(parent_ident)
+    } else if screen.x == parent.right() && screen.y == parent.y {
+        ScreenPositionRelative::Right(parent_ident)
+    } else if screen.right() == parent.x && screen.y == parent.y {
+        ScreenPositionRelative::Left(parent_ident)
+    } else if screen.y == parent.bottom() && screen.x == parent.x {
+        ScreenPositionRelative::Under(parent_ident)
+    } else if screen.bottom() == parent.y && screen.x == parent.x {
+        ScreenPositionRelative::Over(parent_ident)
+    } else if screen.x == parent.right() && screen.y == parent.bottom() {
+        ScreenPositionRelative::RightUnder(parent_ident)
+    } else if screen.x == parent.right() && screen.bottom() == parent.y {
+        ScreenPositionRelative::RightOver(parent_ident)
+    } else if screen.right() == parent.x && screen.y == parent.bottom() {
+        ScreenPositionRelative::LeftUnder(parent_ident)
+    } else if screen.right() == parent.x && screen.bottom() == parent.y {
+        ScreenPositionRelative::LeftOver(parent_ident)
+    }
+    // screens sharing only a part of an edge lose their offset but keep their neighbourhood
+    else if screen.x == parent.right() && screen.overlaps_vertically(parent) {
+        ScreenPositionRelative::Right(parent_ident)
+    } else if screen.right() == parent.x && screen.overlaps_vertically(parent) {
+        ScreenPositionRelative::Left(parent_ident)
+    } else if screen.y == parent.bottom() && screen.overlaps_horizontally(parent) {
+        ScreenPositionRelative::Under(parent_ident)
+    } else if screen.bottom() == parent.y && screen.overlaps_horizontally(parent) {
+        ScreenPositionRelative::Over(parent_ident)
+    } else {
+        return None;
+    };
+    Some(relation)
+}
+
+/// guess a relative position for screens that do not touch any other screen from the direction they are placed in
+fn direction_to(parent: &Rect, screen: &Rect, parent_ident: &str) -> ScreenPositionRelative {
+    let parent_ident = parent_ident.to_string();
+    let (dx, dy) = (
+        screen.center().0 - parent.center().0,
+        screen.center().1 - parent.center().1,
+    );
+    if dx.abs() >= dy.abs() {
+        if dx >= 0 {
+            ScreenPositionRelative::Right(parent_ident)
+        } else {
+            ScreenPositionRelative::Left(parent_ident)
+        }
+    } else if dy >= 0 {
+        ScreenPositionRelative::Under(parent_ident)
+    } else {
+        ScreenPositionRelative::Over(parent_ident)
+    }
+}
+
+/// infer profile positions from the rectangles of the currently active layout
+///
+/// Screens without a rectangle are disabled, they get attached to the root screen as they do not take up any space.
+pub fn infer_relative_positions(screens: &[(String, Option<Rect>)]) -> Vec<ScreenPositionRelative> {
+    if screens.is_empty() {
+        return Vec::new();
+    }
+    let mut positions: Vec<Option<ScreenPositionRelative>> = vec![None; screens.len()];
+
+    let enabled = screens
+        .iter()
+        .enumerate()
+        .filter_map(|(index, (_, rect))| rect.map(|rect| (index, rect)))
+        .collect::<Vec<(usize, Rect)>>();
+
+    // the root screen is the one at the origin of the layout or the top left most one
+    let root = enabled
+        .iter()
+        .find(|(_, rect)| rect.contains((0, 0)))
+        .or_else(|| enabled.iter().min_by_key(|(_, rect)| (rect.y, rect.x)))
+        .map(|(index, _)| *index)
+        .unwrap_or(0);
+    positions[root] = Some(ScreenPositionRelative::Root);
+
+    // walk along touching screens starting at the root screen
+    let mut placed = vec![root];
+    let mut queue = vec![root];
+    while let Some(parent) = queue.pop() {
+        let Some(parent_rect) = screens[parent].1 else {
+            continue;
+        };
+        for (index, rect) in &enabled {
+            if positions[*index].is_some() {
+                continue;
+            }
+            if let Some(relation) = relation_to(&parent_rect, rect, &screens[parent].0) {
+                positions[*index] = Some(relation);
+                placed.push(*index);
+                queue.insert(0, *index);
+            }
+        }
+    }
+
+    // screens that can not be reached are placed next to the nearest screen already placed
+    for (index, rect) in &enabled {
+        if positions[*index].is_some() {
+            continue;
+        }
+        if let Some((parent, parent_rect)) = placed
+            .iter()
+            .filter_map(|parent| screens[*parent].1.map(|parent_rect| (*parent, parent_rect)))
+            .min_by_key(|(_, parent_rect)| {
+                let (dx, dy) = (
+                    rect.center().0 - parent_rect.center().0,
+                    rect.center().1 - parent_rect.center().1,
+                );
+                dx as i64 * dx as i64 + dy as i64 * dy as i64
+            })
+        {
+            positions[*index] = Some(direction_to(&parent_rect, rect, &screens[parent].0));
+            placed.push(*index);
+        }
+    }
+
+    positions
+        .into_iter()
+        .map(|position| {
+            position.unwrap_or_else(|| ScreenPositionRelative::Right(screens[root].0.clone()))
+        })
+        .collect()
+}
+
+/// position that brings a window lying outside of every output back onto the nearest output
+///
+/// The window keeps its relative position inside the previous output it was mostly on. Windows that
+/// were not on any known output are centered. Returns `None` if the window is still visible.
+pub fn rescue_position(
+    window: &Rect,
+    previous_outputs: &[Rect],
+    outputs: &[Rect],
+) -> Option<(i32, i32)> {
+    if outputs
+        .iter()
+        .any(|output| window.intersection(output).is_some())
+    {
+        return None;
+    }
+    let origin = previous_outputs
+        .iter()
+        .filter_map(|output| {
+            window
+                .intersection(output)
+                .map(|area| (output, area.width as i64 * area.height as i64))
+        })
+        .max_by_key(|(_, area)| *area)
+        .map(|(output, _)| output);
+    let from = origin.unwrap_or(window).center();
+    let target = outputs.iter().min_by_key(|output| {
+        let (x, y) = output.center();
+        (x - from.0) as i64 * (x - from.0) as i64 + (y - from.1) as i64 * (y - from.1) as i64
+    })?;
+
+    // share of the free space of the output that lies left of / above the window
+    let relative = |offset: i32, free: i32| {
+        if free > 0 {
+            (offset as f64 / free as f64).clamp(0., 1.)
+        } else {
+            0.5
+        }
+    };
+    let (relative_x, relative_y) = match origin {
+        Some(origin) => (
+            relative(window.x - origin.x, origin.width - window.width),
+            relative(window.y - origin.y, origin.height - window.height),
+        ),
+        None => (0.5, 0.5),
+    };
+    Some((
+        target.x + (relative_x * (target.width - window.width).max(0) as f64).round() as i32,
+        target.y + (relative_y * (target.height - window.height).max(0) as f64).round() as i32,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        infer_relative_positions, repair, rescue_position, solve, validate, Axis, LayoutError,
+        LayoutViolation, PlacedScreen, PositionConstraint, Rect, SolverScreen,
+    };
+    use crate::configuration::ScreenPositionRelative;
+
+    #[test]
+    fn infer_positions_from_layout() {
+        let positions = infer_relative_positions(&[
+            ("eDP-1".to_string(), Some(Rect::new(0, 1440, 1920, 1200))),
+            (
+                "Dell XXXXXXA".to_string(),
+                Some(Rect::new(0, 0, 2560, 1440)),
+            ),
+            (
+                "Dell XXXXXXB".to_string(),
+                Some(Rect::new(2560, 0, 2560, 1440)),
+            ),
+            ("HDMI-A-1".to_string(), None),
+        ]);
+        assert_eq!(
+            positions,
+            vec![
+                ScreenPositionRelative::Under("Dell XXXXXXA".to_string()),
+                ScreenPositionRelative::Root,
+                ScreenPositionRelative::Right("Dell XXXXXXA".to_string()),
+                ScreenPositionRelative::Right("Dell XXXXXXA".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn infer_positions_of_detached_screens() {
+        let positions = infer_relative_positions(&[
+            ("eDP-1".to_string(), Some(Rect::new(0, 0, 1920, 1080))),
+            ("DP-1".to_string(), Some(Rect::new(3000, 100, 1920, 1080))),
+        ]);
+        assert_eq!(
+            positions,
+            vec![
+                ScreenPositionRelative::Root,
+                ScreenPositionRelative::Right("eDP-1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_and_repair_layout() {
+        let screens = [
+            PlacedScreen {
+                identifier: "eDP-1",
+                rect: Rect::new(0, 0, 1920, 1080),
+                mirror: None,
+            },
+            PlacedScreen {
+                identifier: "DP-1",
+                rect: Rect::new(0, 0, 2560, 1440),
+                mirror: None,
+            },
+            PlacedScreen {
+                identifier: "DP-2",
+                rect: Rect::new(0, 0, 2560, 1440),
+                mirror: Some("DP-1"),
+            },
+            PlacedScreen {
+                identifier: "HDMI-A-1",
+                rect: Rect::new(6000, 0, 1920, 1080),
+                mirror: None,
+            },
+        ];
+        assert_eq!(
+            validate(&screens),
+            vec![
+                LayoutViolation::Overlap {
+                    screen: "eDP-1".to_string(),
+                    other: "DP-1".to_string(),
+                    area: Rect::new(0, 0, 1920, 1080)
+                },
+                LayoutViolation::Overlap {
+                    screen: "eDP-1".to_string(),
+                    other: "DP-2".to_string(),
+                    area: Rect::new(0, 0, 1920, 1080)
+                },
+                LayoutViolation::Unreachable {
+                    screen: "HDMI-A-1".to_string()
+                },
+            ]
+        );
+
+        let positions = repair(&screens);
+        assert_eq!(positions, vec![(0, 0), (1920, 0), (1920, 0), (4480, 0)]);
+        let repaired = screens
+            .iter()
+            .zip(positions)
+            .map(|(screen, (x, y))| PlacedScreen {
+                rect: Rect::new(x, y, screen.rect.width, screen.rect.height),
+                ..screen.clone()
+            })
+            .collect::<Vec<PlacedScreen>>();
+        assert!(validate(&repaired).is_empty());
+    }
+
+    #[test]
+    fn repair_next_to_the_rightmost_screen() {
+        let screens = [
+            PlacedScreen {
+                identifier: "A",
+                rect: Rect::new(0, 0, 100, 100),
+                mirror: None,
+            },
+            PlacedScreen {
+                identifier: "B",
+                rect: Rect::new(100, 50, 200, 100),
+                mirror: None,
+            },
+            PlacedScreen {
+                identifier: "C",
+                rect: Rect::new(1000, 60, 100, 40),
+                mirror: None,
+            },
+        ];
+        let positions = repair(&screens);
+        assert_eq!(positions, vec![(0, 0), (100, 50), (300, 50)]);
+        let repaired = screens
+            .iter()
+            .zip(positions)
+            .map(|(screen, (x, y))| PlacedScreen {
+                rect: Rect::new(x, y, screen.rect.width, screen.rect.height),
+                ..screen.clone()
+            })
+            .collect::<Vec<PlacedScreen>>();
+        assert!(validate(&repaired).is_empty());
+    }
+
+    #[test]
+    fn solve_relative_positions() {
+        let screens = [
+            SolverScreen {
+                identifier: "eDP-1",
+                size: (1920, 1200),
+                constraints: ScreenPositionRelative::Under("DP-1".to_string()).constraints(),
+            },
+            SolverScreen {
+                identifier: "DP-1",
+                size: (2560, 1440),
+                constraints: ScreenPositionRelative::Root.constraints(),
+            },
+            SolverScreen {
+                identifier: "DP-2",
+                size: (1440, 2560),
+                constraints: ScreenPositionRelative::Right("DP-1".to_string()).constraints(),
+            },
+            SolverScreen {
+                identifier: "HDMI-A-1",
+                size: (1920, 1080),
+                constraints: ScreenPositionRelative::Left("DP-3".to_string()).constraints(),
+            },
+        ];
+        assert_eq!(
+            solve(&screens),
+            Ok(vec![(0, 1440), (0, 0), (2560, 0), (0, 0)])
+        );
+    }
+
+    #[test]
+    fn solve_multiple_constraints() {
+        let screens = [
+            SolverScreen {
+                identifier: "left",
+                size: (1920, 1080),
+                constraints: vec![PositionConstraint::Origin],
+            },
+            SolverScreen {
+                identifier: "center",
+                size: (2560, 1440),
+                constraints: vec![
+                    PositionConstraint::CenteredBetween("left".to_string(), "right".to_string()),
+                    PositionConstraint::AlignBottom("left".to_string()),
+                    PositionConstraint::AlignBottom("right".to_string()),
+                ],
+            },
+            SolverScreen {
+                identifier: "right",
+                size: (1920, 1080),
+                constraints: vec![
+                    PositionConstraint::RightOf("center".to_string()),
+                    PositionConstraint::AlignTop("left".to_string()),
+                ],
+            },
+            SolverScreen {
+                identifier: "disabled",
+                size: (0, 0),
+                constraints: vec![
+                    PositionConstraint::RightOf("right".to_string()),
+                    PositionConstraint::AlignTop("right".to_string()),
+                ],
+            },
+        ];
+        assert_eq!(
+            solve(&screens),
+            Ok(vec![(0, 0), (1920, -360), (4480, 0), (6400, 0)])
+        );
+    }
+
+    #[test]
+    fn solve_reports_contradictions() {
+        let screens = [
+            SolverScreen {
+                identifier: "left",
+                size: (1920, 1080),
+                constraints: vec![PositionConstraint::Origin],
+            },
+            SolverScreen {
+                identifier: "right",
+                size: (2560, 1440),
+                constraints: vec![
+                    PositionConstraint::AlignTop("left".to_string()),
+                    PositionConstraint::AlignBottom("left".to_string()),
+                ],
+            },
+        ];
+        assert_eq!(
+            solve(&screens),
+            Err(LayoutError::Contradiction {
+                screen: "right".to_string(),
+                constraint: PositionConstraint::AlignBottom("left".to_string()),
+                axis: Axis::Y,
+                required: -360,
+                current: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn solve_reports_unconstrained_screens() {
+        let screens = [
+            SolverScreen {
+                identifier: "left",
+                size: (1920, 1080),
+                constraints: vec![PositionConstraint::Origin],
+            },
+            SolverScreen {
+                identifier: "right",
+                size: (2560, 1440),
+                constraints: Vec::new(),
+            },
+        ];
+        assert_eq!(
+            solve(&screens),
+            Err(LayoutError::Unconstrained {
+                screen: "right".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn rescue_windows_of_removed_outputs() {
+        let laptop = Rect::new(0, 0, 1920, 1080);
+        let previous = [laptop, Rect::new(1920, 0, 2560, 1440)];
+        assert_eq!(
+            rescue_position(&Rect::new(3000, 400, 800, 600), &previous, &[laptop]),
+            Some((687, 229))
+        );
+        assert_eq!(
+            rescue_position(&Rect::new(5000, 5000, 800, 600), &previous, &[laptop]),
+            Some((560, 240))
+        );
+        assert_eq!(
+            rescue_position(&Rect::new(1800, 400, 800, 600), &previous, &[laptop]),
+            None
+        );
+    }
+}
@@ -1,6 +1,10 @@
+use backend::{BackendEvent, BackendKind, WorkspaceBackend};
 use clap::Parser;
-use configuration::{AppConfiguration, ScreensProfile, SwayMonitor};
+use configuration::{AppConfiguration, LayoutTest, OutputBackend, ProfileLayout, ScreensProfile};
+use diagram::DiagramScreen;
+use inputs::InputSettings;
 use itertools::Itertools;
+use layout::{LayoutCheck, LayoutError, Rect};
 use libmonitor::{ddc::DdcDevice, Monitor};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
@@ -16,12 +20,25 @@ use std::{
     sync::{Arc, RwLock},
     thread::sleep,
 };
-use wayland_client::backend::ObjectId;
-use wlr_output_state::MonitorInformation;
+use wlr_output_state::{HeadConfiguration, MonitorInformation, OutputId};
+use workspaces::{MissingWorkspaces, PlacedWorkspaces};
 
+mod backend;
+mod bars;
 mod configuration;
 mod ddc;
+mod diagram;
+mod gnome_output_state;
+mod inputs;
+mod kde_output_state;
+mod layout;
+mod niri_output_state;
+mod snippets;
+mod sway_output_state;
+mod windows;
 mod wlr_output_state;
+mod workspaces;
+mod x11_output_state;
 
 static SOCKET_ADDR: Lazy<String> = Lazy::new(|| {
     env::var("XDG_RUNTIME_DIR")
@@ -41,9 +58,14 @@ static DAEMON_STATE: Lazy<Arc<RwLock<DaemonState>>> =
     Lazy::new(|| Arc::new(RwLock::new(DaemonState::default())));
 
 struct DaemonState {
-    head_state: HashMap<ObjectId, MonitorInformation>,
+    head_state: HashMap<OutputId, MonitorInformation>,
     config: AppConfiguration,
+    config_path: PathBuf,
     current_profile: Option<String>,
+    // workspaces per monitor identifier, kept after the monitor is disconnected
+    workspace_placement: HashMap<String, PlacedWorkspaces>,
+    // settings of the input devices from before the current profile changed them
+    input_restore: BTreeMap<String, InputSettings>,
 }
 
 impl Default for DaemonState {
@@ -51,14 +73,17 @@ impl Default for DaemonState {
         Self {
             head_state: HashMap::new(),
             config: AppConfiguration::default(),
+            config_path: PathBuf::from("workplaces.yml"),
             current_profile: None,
+            workspace_placement: HashMap::new(),
+            input_restore: BTreeMap::new(),
         }
     }
 }
 
 fn get_newest_message<'a>(
-    wlr_rx: &'a mut Receiver<HashMap<ObjectId, MonitorInformation>>,
-) -> Result<HashMap<ObjectId, MonitorInformation>, mpsc::TryRecvError> {
+    wlr_rx: &'a mut Receiver<HashMap<OutputId, MonitorInformation>>,
+) -> Result<HashMap<OutputId, MonitorInformation>, mpsc::TryRecvError> {
     match wlr_rx.try_recv() {
         Ok(head_config) => {
             eprintln!("waiting for new state");
@@ -73,8 +98,8 @@ fn get_newest_message<'a>(
 }
 
 fn connected_monitor_listen(
-    mut wlr_rx: Receiver<HashMap<ObjectId, MonitorInformation>>,
-    config_head_tx: Sender<Vec<(ObjectId, SwayMonitor)>>,
+    mut wlr_rx: Receiver<HashMap<OutputId, MonitorInformation>>,
+    config_head_tx: Sender<HeadConfiguration>,
 ) {
     loop {
         if let Some(current_connected_monitors) = get_newest_message(&mut wlr_rx).ok() {
@@ -83,35 +108,48 @@ fn connected_monitor_listen(
                 current_connected_monitors.keys().collect::<Vec<_>>()
             );
             let mut config_update_tx = config_head_tx.clone();
-            let mut current_monitor_inputs = BTreeMap::new();
-            for mut monitor in Monitor::enumerate() {
-                if let Ok(monitor_input) = monitor.get_input_source() {
-                    current_monitor_inputs.insert(monitor.handle.name(), monitor_input);
+            let current_monitor_inputs = ddc::current_monitor_inputs();
+            let config = DAEMON_STATE
+                .read()
+                .map(|daemon_state| daemon_state.config.clone())
+                .unwrap_or_default();
+            let matching_profiles =
+                config.matching_profiles(&current_connected_monitors, &current_monitor_inputs);
+            // the best profile the compositor does not reject, profiles are only tested if the
+            // configuration asks for it. The daemon state is not locked meanwhile, every test can
+            // take until the compositor answers.
+            let best_profile = matching_profiles.iter().find(|(profile_name, profile)| {
+                if !config.test_profiles() {
+                    return true;
                 }
-            }
+                match profile.test(
+                    &current_connected_monitors,
+                    &current_monitor_inputs,
+                    &config,
+                    &config_update_tx,
+                ) {
+                    Ok(LayoutTest::Rejected(reason)) => {
+                        eprintln!("Compositor rejects profile {profile_name}: {reason}");
+                        false
+                    }
+                    _ => true,
+                }
+            });
             let _ = DAEMON_STATE.clone().write().and_then(|mut daemon_state| {
-                if let Some((profile_name, profile)) = daemon_state
-                    .config
-                    .clone()
-                    .profiles()
-                    .iter()
-                    .filter_map(|(name, profile)| {
-                        eprintln!("Checking if profile {} is connected", name);
-                        if profile
-                            .is_connected(&current_connected_monitors, &current_monitor_inputs)
-                        {
-                            Some((name, profile))
-                        } else {
-                            None
-                        }
-                    })
-                    .sorted_by_key(|profile| profile.1.weight()) // rate matching profiles
-                    .rev() // profile with highest weight should be first
-                    .collect::<Vec<(&String, &ScreensProfile)>>()
-                    .first()
-                {
-                    profile.apply(&current_connected_monitors, &mut config_update_tx);
-                    daemon_state.current_profile = Some(profile_name.to_string());
+                let daemon_state = &mut *daemon_state;
+                restore_workspace_placement(daemon_state, &current_connected_monitors);
+                if let Some((profile_name, profile)) = best_profile {
+                    match profile.apply(
+                        &current_connected_monitors,
+                        &current_monitor_inputs,
+                        &daemon_state.config,
+                        &output_rects(&daemon_state.head_state),
+                        &mut daemon_state.input_restore,
+                        &mut config_update_tx,
+                    ) {
+                        Ok(()) => daemon_state.current_profile = Some(profile_name.to_string()),
+                        Err(err) => eprintln!("Could not apply profile {profile_name}: {err:#}"),
+                    }
                 }
                 eprintln!("apply configuration!");
                 daemon_state.head_state = current_connected_monitors;
@@ -124,6 +162,99 @@ fn connected_monitor_listen(
     }
 }
 
+/// area of the enabled monitors in the compositor layout
+fn output_rects(head_config: &HashMap<OutputId, MonitorInformation>) -> Vec<Rect> {
+    head_config
+        .values()
+        .filter(|monitor_info| monitor_info.enabled() != &0)
+        .map(|monitor_info| monitor_info.logical_rect())
+        .collect()
+}
+
+/// move recorded workspaces back to monitors that were connected before
+///
+/// This runs before the profile is applied, so workspaces pinned by the profile end up on their
+/// configured output.
+fn restore_workspace_placement(
+    daemon_state: &DaemonState,
+    connected_monitors: &HashMap<OutputId, MonitorInformation>,
+) {
+    let returned = connected_monitors
+        .values()
+        .filter(|monitor| {
+            !daemon_state
+                .head_state
+                .values()
+                .any(|known| known.identifier() == monitor.identifier())
+        })
+        .filter_map(|monitor| {
+            daemon_state
+                .workspace_placement
+                .get(&monitor.identifier())
+                .map(|placed| (monitor.name(), placed))
+        })
+        .collect::<Vec<_>>();
+    if returned.is_empty() {
+        return;
+    }
+    if let Some(mut backend) = backend::connect(*daemon_state.config.backend()) {
+        for (output, placed) in returned {
+            if let Ok(current_workspaces) = backend.workspaces() {
+                backend::execute(
+                    backend.as_mut(),
+                    &workspaces::plan_moves(
+                        &current_workspaces,
+                        &placed.moves(output),
+                        MissingWorkspaces::Skip,
+                        placed.visible.as_slice(),
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn record_workspace_placement(backend: &mut dyn WorkspaceBackend) {
+    let _ = backend.workspaces().and_then(|current_workspaces| {
+        let _ = DAEMON_STATE.write().and_then(|mut daemon_state| {
+            let daemon_state = &mut *daemon_state;
+            workspaces::record_placement(
+                &mut daemon_state.workspace_placement,
+                &current_workspaces,
+                |output| {
+                    daemon_state
+                        .head_state
+                        .values()
+                        .find(|monitor| monitor.name() == output)
+                        .map(|monitor| monitor.identifier())
+                },
+            );
+            Ok(())
+        });
+        Ok(())
+    });
+}
+
+fn compositor_listen() {
+    let backend_kind = DAEMON_STATE
+        .read()
+        .map(|daemon_state| *daemon_state.config.backend())
+        .unwrap_or_default();
+    // one connection waits for changes, the other one queries the workspaces
+    if let (Some(mut events), Some(mut backend)) = (
+        backend::connect(backend_kind),
+        backend::connect(backend_kind),
+    ) {
+        record_workspace_placement(backend.as_mut());
+        let result = events.watch(&mut |event| match event {
+            BackendEvent::Workspaces => record_workspace_placement(backend.as_mut()),
+        });
+        if let Err(err) = result {
+            eprintln!("Stopped watching the compositor: {err:#}");
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 struct Options {
@@ -136,6 +267,30 @@ struct Options {
 #[derive(Debug, Parser, Serialize, Deserialize, Clone)]
 struct ProfileSelector {
     name: String,
+    /// Only print the computed layout without applying it
+    #[arg(long)]
+    dry_run: bool,
+    /// Ask the compositor whether it would accept the layout without applying it
+    #[arg(long, conflicts_with = "dry_run")]
+    test: bool,
+}
+
+#[derive(Debug, Parser, Serialize, Deserialize, Clone)]
+struct SaveSelector {
+    /// Name of the new profile
+    name: String,
+    /// Append the profile to the configuration file instead of only printing it
+    #[arg(long)]
+    append: bool,
+}
+
+#[derive(Debug, Parser, Serialize, Deserialize, Clone)]
+struct DiagramSelector {
+    /// Profile to draw, the currently active layout is drawn if no profile is given
+    name: Option<String>,
+    /// Width of the diagram in terminal columns
+    #[arg(long, default_value_t = 80)]
+    width: usize,
 }
 
 #[derive(Debug, Parser, Clone, Serialize, Deserialize)]
@@ -152,13 +307,19 @@ enum Command {
     Pid,
     /// Switch profile to the specified one
     Apply(ProfileSelector),
+    /// Print the layout of the profile that would be selected for the attached monitors
+    Preview,
+    /// Generate a profile from the currently active monitor layout
+    SaveCurrent(SaveSelector),
+    /// Draw the layout of a profile or the currently active layout
+    Diagram(DiagramSelector),
 }
 
 impl Command {
     pub fn run(
         &self,
         buffer: &mut BufWriter<UnixStream>,
-        config_head_tx: &mut Sender<Vec<(ObjectId, SwayMonitor)>>,
+        config_head_tx: &mut Sender<HeadConfiguration>,
     ) {
         match self {
             Command::Attached => {
@@ -214,8 +375,39 @@ impl Command {
             Command::Pid => {
                 let _ = writeln!(buffer, "{}", process::id());
             }
+            Command::Apply(profile_selector) if profile_selector.test => {
+                // the compositor can take until the apply timeout to answer, the daemon state is
+                // not locked meanwhile
+                let (config, head_config) = DAEMON_STATE
+                    .read()
+                    .map(|daemon_state| {
+                        (daemon_state.config.clone(), daemon_state.head_state.clone())
+                    })
+                    .unwrap_or_default();
+                let _ = match config.profiles().get(&profile_selector.name) {
+                    Some(profile) => match profile.test(
+                        &head_config,
+                        &ddc::current_monitor_inputs(),
+                        &config,
+                        config_head_tx,
+                    ) {
+                        Ok(LayoutTest::Accepted) => {
+                            writeln!(buffer, "The compositor accepts the layout")
+                        }
+                        Ok(LayoutTest::Rejected(reason)) => {
+                            writeln!(buffer, "The compositor rejects the layout: {reason}")
+                        }
+                        Ok(LayoutTest::Untested) => {
+                            writeln!(buffer, "The output backend can not test layouts")
+                        }
+                        Err(err) => writeln!(buffer, "{err:#}"),
+                    },
+                    None => writeln!(buffer, "No profile with name {}!", profile_selector.name),
+                };
+            }
             Command::Apply(profile_selector) => {
                 let _ = DAEMON_STATE.write().and_then(|mut daemon_state| {
+                    let daemon_state = &mut *daemon_state;
                     match daemon_state
                         .config
                         .clone()
@@ -224,8 +416,39 @@ impl Command {
                     {
                         Some(profile) => {
                             let head_config = daemon_state.head_state.clone();
-                            profile.apply(&head_config, config_head_tx);
-                            daemon_state.current_profile = Some(profile_selector.name.clone());
+                            let current_monitor_inputs = ddc::current_monitor_inputs();
+                            if profile_selector.dry_run {
+                                write_layout(
+                                    buffer,
+                                    profile.layout(
+                                        &head_config,
+                                        &current_monitor_inputs,
+                                        daemon_state.config.layout_check(),
+                                    ),
+                                );
+                            } else {
+                                match profile.apply(
+                                    &head_config,
+                                    &current_monitor_inputs,
+                                    &daemon_state.config,
+                                    &output_rects(&head_config),
+                                    &mut daemon_state.input_restore,
+                                    config_head_tx,
+                                ) {
+                                    Ok(()) => {
+                                        daemon_state.current_profile =
+                                            Some(profile_selector.name.clone());
+                                        let _ = writeln!(
+                                            buffer,
+                                            "Applied profile {}",
+                                            profile_selector.name
+                                        );
+                                    }
+                                    Err(err) => {
+                                        let _ = writeln!(buffer, "{err:#}");
+                                    }
+                                }
+                            }
                         }
                         None => {
                             let _ =
@@ -235,11 +458,208 @@ impl Command {
                     Ok(())
                 });
             }
+            Command::Preview => {
+                let _ = DAEMON_STATE.read().and_then(|daemon_state| {
+                    let current_monitor_inputs = ddc::current_monitor_inputs();
+                    match daemon_state
+                        .config
+                        .matching_profiles(&daemon_state.head_state, &current_monitor_inputs)
+                        .first()
+                    {
+                        Some((profile_name, profile)) => {
+                            let _ = writeln!(buffer, "Profile: {profile_name}");
+                            write_layout(
+                                buffer,
+                                profile.layout(
+                                    &daemon_state.head_state,
+                                    &current_monitor_inputs,
+                                    daemon_state.config.layout_check(),
+                                ),
+                            );
+                        }
+                        None => {
+                            let _ = writeln!(buffer, "No profile matches the attached monitors!");
+                        }
+                    }
+                    Ok(())
+                });
+            }
+            Command::Diagram(diagram_selector) => {
+                let _ = DAEMON_STATE.read().and_then(|daemon_state| {
+                    let screens = match &diagram_selector.name {
+                        Some(profile_name) => {
+                            match daemon_state.config.profiles().get(profile_name) {
+                                Some(profile) => match profile_diagram_screens(
+                                    profile,
+                                    &daemon_state.head_state,
+                                    daemon_state.config.layout_check(),
+                                ) {
+                                    Ok(screens) => screens,
+                                    Err(err) => {
+                                        let _ = writeln!(buffer, "Invalid layout: {err}");
+                                        return Ok(());
+                                    }
+                                },
+                                None => {
+                                    let _ =
+                                        writeln!(buffer, "No profile with name {profile_name}!");
+                                    return Ok(());
+                                }
+                            }
+                        }
+                        None => live_diagram_screens(
+                            &daemon_state.head_state,
+                            *daemon_state.config.backend(),
+                        ),
+                    };
+                    let _ = write!(
+                        buffer,
+                        "{}",
+                        diagram::render(&screens, diagram_selector.width)
+                    );
+                    Ok(())
+                });
+            }
+            Command::SaveCurrent(save_selector) => {
+                let _ = DAEMON_STATE.write().and_then(|mut daemon_state| {
+                    let profile = ScreensProfile::from_head_config(&daemon_state.head_state);
+                    if !save_selector.append {
+                        let _ = writeln!(
+                            buffer,
+                            "{}",
+                            serde_yaml::to_string(&BTreeMap::from([(
+                                &save_selector.name,
+                                &profile
+                            )]))
+                            .unwrap()
+                        );
+                    } else if daemon_state
+                        .config
+                        .profiles()
+                        .contains_key(&save_selector.name)
+                    {
+                        let _ = writeln!(
+                            buffer,
+                            "Profile with name {} already exists!",
+                            save_selector.name
+                        );
+                    } else {
+                        let config_path = daemon_state.config_path.clone();
+                        match append_profile_to_config(&config_path, &save_selector.name, &profile)
+                        {
+                            Ok(()) => {
+                                let _ = writeln!(
+                                    buffer,
+                                    "Added profile {} to {}",
+                                    save_selector.name,
+                                    config_path.display()
+                                );
+                                daemon_state
+                                    .config
+                                    .add_profile(save_selector.name.clone(), profile);
+                            }
+                            Err(err) => {
+                                let _ = writeln!(buffer, "Could not save profile! {err:#}");
+                            }
+                        }
+                    }
+                    Ok(())
+                });
+            }
         }
     }
 }
 
-fn command_listener(mut head_config_tx: Sender<Vec<(ObjectId, SwayMonitor)>>) {
+fn write_layout(buffer: &mut BufWriter<UnixStream>, layout: Result<ProfileLayout, LayoutError>) {
+    let _ = match layout {
+        Ok(layout) => write!(buffer, "{layout}"),
+        Err(err) => writeln!(buffer, "Invalid layout: {err}"),
+    };
+}
+
+fn profile_diagram_screens(
+    profile: &ScreensProfile,
+    head_config: &HashMap<OutputId, MonitorInformation>,
+    layout_check: &LayoutCheck,
+) -> Result<Vec<DiagramScreen>, LayoutError> {
+    Ok(profile
+        .layout(head_config, &ddc::current_monitor_inputs(), layout_check)?
+        .monitors
+        .into_iter()
+        .filter(|(_, monitor)| monitor.enabled)
+        .map(|(_, monitor)| DiagramScreen {
+            identifier: monitor.identifier.clone(),
+            rect: monitor.rect(),
+            labels: vec![
+                monitor.identifier.clone(),
+                format!("{}x{}@{:.0}Hz", monitor.width, monitor.height, monitor.fps),
+                format!("scale {}", monitor.scale),
+                format!(
+                    "ws: {}",
+                    monitor.workspaces.iter().map(|ws| ws.to_string()).join(" ")
+                ),
+            ],
+            mirror: monitor.mirror,
+        })
+        .collect())
+}
+
+fn live_diagram_screens(
+    head_config: &HashMap<OutputId, MonitorInformation>,
+    backend_kind: Option<BackendKind>,
+) -> Vec<DiagramScreen> {
+    let workspaces = backend::connect(backend_kind)
+        .and_then(|mut backend| backend.workspaces().ok())
+        .unwrap_or_default();
+    head_config
+        .values()
+        .filter(|monitor_info| *monitor_info.enabled() != 0)
+        .sorted_by_key(|monitor_info| monitor_info.name().clone())
+        .map(|monitor_info| {
+            let mode = monitor_info.active_mode();
+            DiagramScreen {
+                identifier: monitor_info.identifier(),
+                rect: monitor_info.logical_rect(),
+                labels: vec![
+                    monitor_info.identifier(),
+                    format!(
+                        "{}x{}@{:.0}Hz",
+                        mode.size().0,
+                        mode.size().1,
+                        mode.refresh() / 1000.
+                    ),
+                    format!("scale {}", monitor_info.scale()),
+                    format!(
+                        "ws: {}",
+                        workspaces
+                            .iter()
+                            .filter(|ws| &ws.output == monitor_info.name())
+                            .map(|ws| ws.name.clone())
+                            .join(" ")
+                    ),
+                ],
+                mirror: None,
+            }
+        })
+        .collect()
+}
+
+fn append_profile_to_config(
+    config_path: &Path,
+    name: &str,
+    profile: &ScreensProfile,
+) -> anyhow::Result<()> {
+    let config_text = match std::fs::read_to_string(config_path) {
+        Ok(config_text) => config_text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let config_text = configuration::insert_profile_into_config(&config_text, name, profile)?;
+    std::fs::write(config_path, config_text)?;
+    Ok(())
+}
+
+fn command_listener(mut head_config_tx: Sender<HeadConfiguration>) {
     let _ = UnixListener::bind(SOCKET_ADDR.as_str()).and_then(|socket_server| {
         for connection in socket_server.incoming() {
             let _ = connection.and_then(|mut stream| {
@@ -323,6 +743,7 @@ fn main() {
                 .config
                 .unwrap_or(Path::new("workplaces.yml").into());
             let _ = DAEMON_STATE.write().and_then(|mut daemon_state| {
+                daemon_state.config_path = config_path.clone();
                 let _ = File::open(config_path).and_then(|file_reader| {
                     daemon_state.config = serde_yaml::from_reader(file_reader)
                         .expect("Could not parse workspace profiles!");
@@ -342,24 +763,71 @@ fn main() {
                 let _ = std::fs::remove_file(&socket_path);
             }
 
-            let (wlr_tx, wlr_rx) = mpsc::channel::<HashMap<ObjectId, MonitorInformation>>();
+            let (wlr_tx, wlr_rx) = mpsc::channel::<HashMap<OutputId, MonitorInformation>>();
 
-            let (head_config_tx, head_config_rx) = mpsc::channel::<Vec<(ObjectId, SwayMonitor)>>();
+            let (head_config_tx, head_config_rx) = mpsc::channel::<HeadConfiguration>();
 
             let head_config_command_tx = head_config_tx.clone();
 
-            let wlr_output_updates_blocking = std::thread::spawn(|| {
-                wlr_output_state::wayland_event_loop(wlr_tx, head_config_rx);
+            let output_backend = DAEMON_STATE
+                .read()
+                .map(|daemon_state| *daemon_state.config.output_backend())
+                .unwrap_or_default();
+            let wlr_output_updates_blocking = std::thread::spawn(move || {
+                let output_backend = match output_backend {
+                    OutputBackend::Wlr => {
+                        match wlr_output_state::wayland_event_loop(&wlr_tx, &head_config_rx) {
+                            Ok(()) => return,
+                            Err(err) => eprintln!("{err:#}, managing outputs over ipc"),
+                        }
+                        match BackendKind::detect() {
+                            _ if env::var_os("WAYLAND_DISPLAY").is_none()
+                                && env::var_os("DISPLAY").is_some() =>
+                            {
+                                OutputBackend::X11
+                            }
+                            Some(BackendKind::Niri) => OutputBackend::Niri,
+                            Some(BackendKind::Sway) => OutputBackend::Sway,
+                            _ if env::var("XDG_CURRENT_DESKTOP")
+                                .is_ok_and(|desktop| desktop.contains("GNOME")) =>
+                            {
+                                OutputBackend::Gnome
+                            }
+                            _ => OutputBackend::Kde,
+                        }
+                    }
+                    ipc => ipc,
+                };
+                let result = match output_backend {
+                    OutputBackend::Niri => {
+                        niri_output_state::niri_event_loop(&wlr_tx, &head_config_rx)
+                    }
+                    OutputBackend::Kde => {
+                        kde_output_state::kde_event_loop(&wlr_tx, &head_config_rx)
+                    }
+                    OutputBackend::Gnome => {
+                        gnome_output_state::gnome_event_loop(&wlr_tx, &head_config_rx)
+                    }
+                    OutputBackend::X11 => {
+                        x11_output_state::x11_event_loop(&wlr_tx, &head_config_rx)
+                    }
+                    _ => sway_output_state::sway_event_loop(&wlr_tx, &head_config_rx),
+                };
+                if let Err(err) = result {
+                    eprintln!("Could not manage outputs over {output_backend:?} ipc: {err:#}");
+                }
             });
             let commmand_listener_task = std::thread::spawn(|| {
                 command_listener(head_config_command_tx);
             });
             let connected_monitors_handler =
                 std::thread::spawn(|| connected_monitor_listen(wlr_rx, head_config_tx));
+            let compositor_task = std::thread::spawn(compositor_listen);
 
             let _ = wlr_output_updates_blocking.join();
             let _ = connected_monitors_handler.join();
             let _ = commmand_listener_task.join();
+            let _ = compositor_task.join();
         }
     }
 }
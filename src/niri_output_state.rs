@@ -0,0 +1,37 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, sleep},
+};
+
+use crate::{
+    backend::{NiriBackend, WorkspaceBackend},
+    wlr_output_state::{output_event_loop, HeadConfiguration, MonitorInformation, OutputId},
+};
+
+// niri does not send events for outputs, they are polled instead
+const POLL_INTERVAL: u64 = 1000;
+
+/// follow and configure the outputs over the niri ipc socket
+pub fn niri_event_loop(
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+) -> anyhow::Result<()> {
+    let backend = NiriBackend::connect()?;
+    let mut command_backend = NiriBackend::connect()?;
+    let (poll_tx, poll_rx) = mpsc::channel();
+    thread::spawn(move || {
+        while poll_tx.send(()).is_ok() {
+            sleep(std::time::Duration::from_millis(POLL_INTERVAL));
+        }
+    });
+
+    output_event_loop(
+        wlr_tx,
+        config_head_rx,
+        &poll_rx,
+        false,
+        || backend.monitors(),
+        |monitors, _| command_backend.configure_outputs(monitors).map(|_| ()),
+    )
+}
@@ -0,0 +1,212 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend,
+    configuration::{shell_command, ProfileLayout, SwayMonitor},
+    workspaces::quote,
+};
+
+/// how a selected layout is put into effect
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub enum OutputMode {
+    /// configure outputs and workspaces of the running compositor directly
+    #[default]
+    Live,
+    /// write the layout into a configuration file and let the compositor reload it
+    File(ConfigFile),
+}
+
+impl OutputMode {
+    pub fn is_live(&self) -> bool {
+        *self == OutputMode::Live
+    }
+}
+
+/// syntax of the written configuration file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    /// `output` and `workspace` lines to `include` from the sway configuration
+    Sway,
+    /// `monitor` and `workspace` rules to `source` from the hyprland configuration
+    Hyprland,
+    /// a kanshi profile
+    Kanshi,
+}
+
+/// configuration file the layout is rendered into
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConfigFile {
+    pub format: ConfigFormat,
+    pub path: PathBuf,
+    /// command run after writing the file, defaults to the reload command of the format
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reload: Option<String>,
+}
+
+impl ConfigFile {
+    /// write the layout and trigger the reload
+    pub fn write(&self, layout: &ProfileLayout) -> io::Result<()> {
+        fs::write(&self.path, render(self.format, layout))?;
+        let reload = self.reload.as_deref().unwrap_or(match self.format {
+            ConfigFormat::Sway => "swaymsg reload",
+            ConfigFormat::Hyprland => "hyprctl reload",
+            ConfigFormat::Kanshi => "kanshictl reload",
+        });
+        shell_command(reload).output()?;
+        Ok(())
+    }
+}
+
+/// configuration file content for the monitors and workspace rules of a layout
+pub fn render(format: ConfigFormat, layout: &ProfileLayout) -> String {
+    let monitors = layout
+        .monitors
+        .iter()
+        .map(|(_, hm)| hm.clone())
+        .collect::<Vec<SwayMonitor>>();
+    let mut lines = Vec::new();
+    match format {
+        ConfigFormat::Sway => {
+            for monitor in &monitors {
+                lines.push(output_command(monitor));
+            }
+            for (ws, outputs) in &layout.workspace_rules {
+                lines.push(format!(
+                    "workspace {} output {}",
+                    quote(ws.as_str()),
+                    outputs
+                        .iter()
+                        .map(|output| quote(output))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                ));
+            }
+        }
+        ConfigFormat::Hyprland => {
+            for monitor in &monitors {
+                lines.push(format!(
+                    "monitor={}",
+                    backend::monitor_rule(monitor, &monitors)
+                ));
+            }
+            // workspace rules of hyprland only take a single monitor
+            for (ws, outputs) in &layout.workspace_rules {
+                if let Some(output) = outputs.first() {
+                    lines.push(format!("workspace=name:{ws}, monitor:{output}"));
+                }
+            }
+        }
+        ConfigFormat::Kanshi => {
+            lines.push("profile sway-autodesktop {".to_string());
+            for monitor in &monitors {
+                lines.push(format!("    output {}", output_options(monitor, true)));
+            }
+            lines.push("}".to_string());
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// sway `output` command configuring a monitor of the layout
+pub fn output_command(monitor: &SwayMonitor) -> String {
+    format!("output {}", output_options(monitor, false))
+}
+
+/// output name with the settings of the monitor in sway or kanshi syntax
+fn output_options(monitor: &SwayMonitor, kanshi: bool) -> String {
+    if !monitor.enabled {
+        return format!("{} disable", quote(&monitor.name));
+    }
+    let transform = match monitor.rotation.degrees() {
+        0 => "normal".to_string(),
+        degrees => degrees.to_string(),
+    };
+    let position = if kanshi {
+        format!("position {},{}", monitor.pos_x, monitor.pos_y)
+    } else {
+        format!("pos {} {}", monitor.pos_x, monitor.pos_y)
+    };
+    format!(
+        "{} enable mode {}x{}@{}Hz {position} scale {} transform {transform}",
+        quote(&monitor.name),
+        monitor.width,
+        monitor.height,
+        monitor.fps,
+        monitor.scale
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, ConfigFormat, OutputMode};
+    use crate::{
+        configuration::{ProfileLayout, ScreenRotation, SwayMonitor},
+        wlr_output_state::OutputId,
+        workspaces::WorkspaceName,
+    };
+
+    fn monitor(name: &str, enabled: bool) -> (OutputId, SwayMonitor) {
+        (
+            OutputId::Name(name.to_string()),
+            SwayMonitor {
+                identifier: format!("Dell {name}"),
+                mirror: None,
+                enabled,
+                name: name.to_string(),
+                width: 2560,
+                height: 1440,
+                fps: 60.0,
+                pos_x: 1920,
+                pos_y: 0,
+                scale: 1.5,
+                rotation: ScreenRotation::Portrait,
+                workspaces: Vec::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn render_config_snippets() {
+        let layout = ProfileLayout {
+            monitors: vec![monitor("DP-1", true), monitor("eDP-1", false)],
+            workspace_moves: Vec::new(),
+            workspace_rules: vec![(
+                WorkspaceName::new("web"),
+                vec!["DP-1".to_string(), "eDP-1".to_string()],
+            )],
+            window_commands: Vec::new(),
+            input_mappings: Vec::new(),
+            output_commands: Vec::new(),
+            bar_commands: Vec::new(),
+            waybar_outputs: Vec::new(),
+            input_changes: Vec::new(),
+            violations: Vec::new(),
+            repaired: false,
+        };
+        assert_eq!(
+            render(ConfigFormat::Sway, &layout),
+            "output \"DP-1\" enable mode 2560x1440@60Hz pos 1920 0 scale 1.5 transform 90\n\
+             output \"eDP-1\" disable\n\
+             workspace \"web\" output \"DP-1\" \"eDP-1\"\n"
+        );
+        assert_eq!(
+            render(ConfigFormat::Hyprland, &layout),
+            "monitor=DP-1,2560x1440@60,1920x0,1.5,transform,1\n\
+             monitor=eDP-1,disable\n\
+             workspace=name:web, monitor:DP-1\n"
+        );
+        assert_eq!(
+            render(ConfigFormat::Kanshi, &layout),
+            "profile sway-autodesktop {\n    \
+             output \"DP-1\" enable mode 2560x1440@60Hz position 1920,0 scale 1.5 transform 90\n    \
+             output \"eDP-1\" disable\n\
+             }\n"
+        );
+        let mode: OutputMode =
+            serde_yaml::from_str("!File { format: Kanshi, path: /tmp/kanshi }").unwrap();
+        assert!(matches!(mode, OutputMode::File(file) if file.reload.is_none()));
+    }
+}
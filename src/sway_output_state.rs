@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use swayipc::{Connection, EventType, Output};
+use wayland_client::protocol::wl_output::Transform;
+
+use crate::{
+    configuration::SwayMonitor,
+    snippets,
+    wlr_output_state::{
+        output_event_loop, HeadConfiguration, MonitorInformation, MonitorInformationBuilder,
+        MonitorModeBuilder, OutputId,
+    },
+};
+
+fn transform(transform: Option<&str>) -> Transform {
+    match transform {
+        Some("90") => Transform::_90,
+        Some("180") => Transform::_180,
+        Some("270") => Transform::_270,
+        Some("flipped") => Transform::Flipped,
+        Some("flipped-90") => Transform::Flipped90,
+        Some("flipped-180") => Transform::Flipped180,
+        Some("flipped-270") => Transform::Flipped270,
+        _ => Transform::Normal,
+    }
+}
+
+/// monitor information of an output reported by sway
+///
+/// Sway does not report a preferred mode, its first mode is used like for wlr heads without one.
+fn monitor_information(output: &Output) -> anyhow::Result<MonitorInformation> {
+    let mut builder = MonitorInformationBuilder::default();
+    builder
+        .name(output.name.clone())
+        .make(output.make.clone())
+        .model(output.model.clone())
+        .description(format!(
+            "{} {} {} ({})",
+            output.make, output.model, output.serial, output.name
+        ))
+        .position((output.rect.x, output.rect.y))
+        .enabled(output.active as i32)
+        .transform(transform(output.transform.as_deref()))
+        // disabled outputs report a scale of -1
+        .scale(output.scale.filter(|scale| *scale > 0.).unwrap_or(1.))
+        .serial(Some(output.serial.clone()));
+    let mut current_found = false;
+    for mode in &output.modes {
+        let current = output.current_mode.is_some_and(|current_mode| {
+            current_mode.width == mode.width
+                && current_mode.height == mode.height
+                && current_mode.refresh == mode.refresh
+        });
+        current_found |= current;
+        builder.add_mode(
+            MonitorModeBuilder::default()
+                .size((mode.width, mode.height))
+                .refresh(mode.refresh)
+                .current(current)
+                .build()?,
+        );
+    }
+    // custom modes are not part of the mode list
+    if let (false, Some(current_mode)) = (current_found, output.current_mode) {
+        builder.add_mode(
+            MonitorModeBuilder::default()
+                .size((current_mode.width, current_mode.height))
+                .refresh(current_mode.refresh)
+                .current(true)
+                .build()?,
+        );
+    }
+    Ok(builder.build()?)
+}
+
+fn current_configuration(
+    connection: &mut Connection,
+) -> anyhow::Result<HashMap<OutputId, MonitorInformation>> {
+    let mut configuration = HashMap::new();
+    for output in connection.get_outputs()? {
+        configuration.insert(
+            OutputId::Name(output.name.clone()),
+            monitor_information(&output)?,
+        );
+    }
+    Ok(configuration)
+}
+
+/// follow and configure the outputs over the sway ipc socket
+pub fn sway_event_loop(
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+) -> anyhow::Result<()> {
+    let mut connection = Connection::new()?;
+    let mut command_connection = Connection::new()?;
+    let events = Connection::new()?.subscribe([EventType::Output])?;
+    let (event_tx, event_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for event in events {
+            if event.is_err() || event_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    output_event_loop(
+        wlr_tx,
+        config_head_rx,
+        &event_rx,
+        false,
+        || current_configuration(&mut connection),
+        |monitors, _| configure(&mut command_connection, monitors),
+    )
+}
+
+/// run the output command of every monitor, failed commands are collected into the error
+fn configure(connection: &mut Connection, monitors: &[SwayMonitor]) -> anyhow::Result<()> {
+    let mut failures = Vec::new();
+    for monitor in monitors {
+        let command = snippets::output_command(monitor);
+        for outcome in connection.run_command(&command)? {
+            if let Err(err) = outcome {
+                failures.push(format!("{command} failed: {err}"));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        anyhow::bail!(failures.join(", "));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::monitor_information;
+    use swayipc::Output;
+
+    #[test]
+    fn sway_output_information() {
+        let output: Output = serde_json::from_str(
+            r#"{
+                "id": 3, "name": "DP-1", "make": "Dell Inc.", "model": "DELL U2720Q",
+                "serial": "XXXXXXA", "active": true, "dpms": true, "primary": false,
+                "scale": 1.5, "transform": "90", "current_workspace": "1",
+                "modes": [
+                    {"width": 3840, "height": 2160, "refresh": 59997},
+                    {"width": 2560, "height": 1440, "refresh": 59951}
+                ],
+                "current_mode": {"width": 2560, "height": 1440, "refresh": 59951},
+                "rect": {"x": 1920, "y": 0, "width": 960, "height": 1707}
+            }"#,
+        )
+        .unwrap();
+        let monitor_info = monitor_information(&output).unwrap();
+        assert_eq!(monitor_info.identifier(), "Dell Inc. XXXXXXA");
+        assert_eq!(*monitor_info.preffered_mode().size(), (3840, 2160));
+        assert_eq!(*monitor_info.active_mode().size(), (2560, 1440));
+        assert_eq!(
+            monitor_info.logical_rect(),
+            crate::layout::Rect::new(1920, 0, 960, 1707)
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    layout::{self, Rect},
+    workspaces::{quote, WorkspaceName},
+};
+
+/// what happens to floating windows that are outside of every output after a profile is applied
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum FloatingWindows {
+    /// move them to the nearest output
+    #[default]
+    Rescue,
+    Keep,
+}
+
+impl FloatingWindows {
+    pub fn is_rescue(&self) -> bool {
+        *self == FloatingWindows::Rescue
+    }
+}
+
+/// where matching windows are moved to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WindowTarget {
+    /// screen identifier of the profile or output name
+    Output(String),
+    Workspace(WorkspaceName),
+}
+
+/// application windows moved when a profile is applied
+///
+/// All criteria are regular expressions and have to match, like sway criteria.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub target: WindowTarget,
+    /// center the matching floating windows on their new output
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub center_floating: bool,
+}
+
+impl WindowRule {
+    /// sway criteria selecting the windows of this rule, `None` if the rule has no criteria
+    pub fn criteria(&self) -> Option<String> {
+        let criteria = [
+            ("app_id", &self.app_id),
+            ("class", &self.class),
+            ("title", &self.title),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            value
+                .as_ref()
+                .map(|value| format!("{key}={}", quote(value)))
+        })
+        .collect::<Vec<String>>();
+        (!criteria.is_empty()).then(|| criteria.join(" "))
+    }
+
+    /// sway commands moving the matching windows to the output or workspace
+    ///
+    /// Floating windows are only centered if the rule asks for it, windows left outside of every
+    /// output are rescued anyway.
+    pub fn commands(&self, output: Option<&str>) -> Vec<String> {
+        let Some(criteria) = self.criteria() else {
+            return Vec::new();
+        };
+        let destination = match (&self.target, output) {
+            (WindowTarget::Output(_), Some(output)) => format!("output {}", quote(output)),
+            (WindowTarget::Output(_), None) => return Vec::new(),
+            (WindowTarget::Workspace(ws), _) => match ws.as_str().parse::<u32>() {
+                Ok(num) => format!("workspace number {num}"),
+                Err(_) => format!("workspace {}", quote(ws.as_str())),
+            },
+        };
+        let mut commands = vec![format!("[{criteria}] move container to {destination}")];
+        if self.center_floating {
+            commands.push(format!("[{criteria} floating] move position center"));
+        }
+        commands
+    }
+}
+
+/// sway commands moving floating windows that are outside of every output onto the nearest output
+pub fn rescue_commands(
+    windows: &[(i64, Rect)],
+    previous_outputs: &[Rect],
+    outputs: &[Rect],
+) -> Vec<String> {
+    windows
+        .iter()
+        .filter_map(|(id, rect)| {
+            layout::rescue_position(rect, previous_outputs, outputs)
+                .map(|(x, y)| format!("[con_id={id}] move absolute position {x} {y}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{WindowRule, WindowTarget};
+    use crate::workspaces::WorkspaceName;
+
+    #[test]
+    fn window_rule_commands() {
+        let rules: Vec<WindowRule> = serde_yaml::from_str(
+            "- { app_id: ^Slack$, target: !Output Dell XXXXXXA, center_floating: true }\n\
+             - { class: zoom, title: Meeting.*, target: !Workspace 3 }\n\
+             - { target: !Workspace mail }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            rules[1].target,
+            WindowTarget::Workspace(WorkspaceName::new("3"))
+        );
+        assert_eq!(
+            rules[0].commands(Some("DP-2")),
+            [
+                "[app_id=\"^Slack$\"] move container to output \"DP-2\"",
+                "[app_id=\"^Slack$\" floating] move position center",
+            ]
+        );
+        assert!(rules[0].commands(None).is_empty());
+        assert_eq!(
+            rules[1].commands(None),
+            ["[class=\"zoom\" title=\"Meeting.*\"] move container to workspace number 3"]
+        );
+        assert!(rules[2].commands(None).is_empty());
+    }
+}
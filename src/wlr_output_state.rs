@@ -1,5 +1,11 @@
-use std::{collections::HashMap, thread::sleep};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    thread::sleep,
+    time::{Duration, Instant},
+};
 
+use anyhow::bail;
 use derive_builder::Builder;
 use derive_getters::Getters;
 use std::sync::mpsc::{Receiver, Sender};
@@ -17,25 +23,94 @@ use wayland_protocols_wlr::output_management::{
     },
 };
 
-use crate::configuration::SwayMonitor;
+use crate::configuration::{ScreenRotation, SwayMonitor, APPLY_TIMEOUT};
+use crate::layout::Rect;
+
+// vendor names for the pnp ids of the edid, the same list wlroots takes the make of a monitor from
+const PNP_IDS: &str = "/usr/share/hwdata/pnp.ids";
+
+/// vendor names by pnp id, empty if hwdata is not installed
+pub fn read_pnp_ids() -> String {
+    fs::read_to_string(PNP_IDS).unwrap_or_default()
+}
+
+/// make of a monitor the way wlroots reports it, the vendor name of the pnp id if it is known
+pub fn pnp_vendor(pnp_id: &str, pnp_ids: &str) -> String {
+    pnp_ids
+        .lines()
+        .find_map(|line| line.strip_prefix(&format!("{pnp_id}\t")))
+        .unwrap_or(pnp_id)
+        .to_string()
+}
+
+/// key of a monitor, the wlr output head, the kde output device or the output name if the outputs
+/// are managed over ipc
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OutputId {
+    Wlr(ObjectId),
+    Kde(ObjectId),
+    Name(String),
+}
+
+/// layout handed to the output state thread, the outcome of applying it is sent back over
+/// `result_tx`
+///
+/// Backends that can not tell whether the configuration was applied just drop the sender. A
+/// `test` configuration is only checked by the compositor.
+pub struct HeadConfiguration {
+    pub monitors: Vec<(OutputId, SwayMonitor)>,
+    pub test: bool,
+    pub result_tx: Sender<anyhow::Result<()>>,
+}
+
+impl HeadConfiguration {
+    /// the configuration if it is to be applied, for backends without a test request
+    ///
+    /// Tests are dropped unanswered, the daemon reports the layout as untested then.
+    pub fn applicable(self) -> Option<Self> {
+        (!self.test).then_some(self)
+    }
+}
+
+// how often a configuration cancelled by a concurrent output change is sent again
+const CONFIGURATION_RETRIES: usize = 3;
+
+/// configuration sent to the compositor that did not get an answer yet
+struct PendingConfiguration {
+    configuration: HeadConfiguration,
+    // configuration object the compositor answers on
+    id: ObjectId,
+    retries: usize,
+    // the daemon stops waiting for the answer at this point
+    deadline: Instant,
+}
+
+impl PendingConfiguration {
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_millis(APPLY_TIMEOUT)
+    }
+}
 
 #[derive(Builder, Debug, Clone, Getters)]
 #[allow(dead_code)]
 pub struct MonitorMode {
-    #[builder(setter(into))]
-    mode: ZwlrOutputModeV1,
+    #[builder(setter(into, strip_option), default)]
+    mode: Option<ZwlrOutputModeV1>,
     #[builder(setter(into))]
     size: (i32, i32),
     #[builder(setter(into))]
     refresh: f64,
     #[builder(setter(into), default)]
     preferred: bool,
+    /// set for the current mode of outputs that are not managed over wlr
+    #[builder(setter(into), default)]
+    current: bool,
 }
 
 #[derive(Builder, Debug, Clone, Getters)]
 pub struct MonitorInformation {
-    #[builder(setter(into))]
-    head: ZwlrOutputHeadV1,
+    #[builder(setter(into, strip_option), default)]
+    head: Option<ZwlrOutputHeadV1>,
     #[builder(setter(into), default)]
     name: String,
     #[builder(setter(into), default)]
@@ -58,8 +133,8 @@ pub struct MonitorInformation {
     serial: Option<String>,
     #[builder(setter(into), default)]
     adaptive_sync: Option<AdaptiveSyncState>,
-    #[builder(setter(into))]
-    current_mode: ObjectId,
+    #[builder(setter(into, strip_option), default)]
+    current_mode: Option<ObjectId>,
     #[builder(setter(into), default)]
     modes: Vec<MonitorMode>,
 }
@@ -75,6 +150,53 @@ impl MonitorInformation {
         &self.modes[0]
     }
 
+    /// mode the monitor is currently running in, falls back to the preffered mode if it is not known
+    pub fn active_mode(&self) -> &MonitorMode {
+        self.modes
+            .iter()
+            .find(|mode| {
+                mode.current
+                    || (mode.mode.as_ref().map(|mode| mode.id()) == self.current_mode
+                        && self.current_mode.is_some())
+            })
+            .unwrap_or_else(|| self.preffered_mode())
+    }
+
+    /// identifier used to refer to this monitor in profiles, prefers make and serial over the connector name
+    pub fn identifier(&self) -> String {
+        match &self.serial {
+            Some(serial) if !serial.is_empty() => format!("{} {}", self.make, serial),
+            _ => self.name.clone(),
+        }
+    }
+
+    /// check if the monitor is referred to by a profile identifier
+    pub fn matches(&self, identifier: &str) -> bool {
+        identifier == self.name
+            || identifier
+                == format!(
+                    "{} {}",
+                    self.make,
+                    self.serial.as_ref().unwrap_or(&"".to_string())
+                )
+    }
+
+    /// area the monitor currently occupies in the compositor layout (in logical pixels)
+    pub fn logical_rect(&self) -> Rect {
+        Rect::logical(
+            self.position,
+            ScreenRotation::from(self.transform).transform_size(*self.active_mode().size()),
+            self.scale,
+        )
+    }
+
+    /// rectangle the profile layout places the monitor in, sized by its unscaled preferred mode
+    pub fn layout_rect(&self) -> Rect {
+        let (width, height) =
+            ScreenRotation::from(self.transform).transform_size(*self.preffered_mode().size());
+        Rect::new(self.position.0, self.position.1, width, height)
+    }
+
     #[allow(dead_code)]
     pub fn biggest_mode(&self) -> &MonitorMode {
         let mut biggest_mode: &MonitorMode = &self.modes[0];
@@ -122,20 +244,36 @@ impl MonitorInformationBuilder {
     }
 }
 
+/// index of the mode with the given size and the refresh rate closest to `fps`
+pub fn closest_mode(modes: &[MonitorMode], size: (i32, i32), fps: f64) -> Option<usize> {
+    modes
+        .iter()
+        .enumerate()
+        .filter(|(_, mode)| *mode.size() == size)
+        .min_by(|(_, a), (_, b)| {
+            (a.refresh() / 1000. - fps)
+                .abs()
+                .total_cmp(&(b.refresh() / 1000. - fps).abs())
+        })
+        .map(|(index, _)| index)
+}
+
 struct ScreenManagerState {
     running: bool,
     _display: WlDisplay,
     update_serial: u32,
     output_manager: Option<zwlr_output_manager_v1::ZwlrOutputManagerV1>,
-    wlr_tx: Sender<HashMap<ObjectId, MonitorInformation>>,
+    wlr_tx: Sender<HashMap<OutputId, MonitorInformation>>,
     config_dirty: bool,
     current_head: Option<MonitorInformationBuilder>,
     current_mode: Option<MonitorModeBuilder>,
-    current_configuration: HashMap<ObjectId, MonitorInformation>,
+    current_configuration: HashMap<OutputId, MonitorInformation>,
+    pending_configuration: Option<PendingConfiguration>,
+    configuration_result: Option<zwlr_output_configuration_v1::Event>,
 }
 
 impl ScreenManagerState {
-    pub fn new(display: WlDisplay, wlr_tx: Sender<HashMap<ObjectId, MonitorInformation>>) -> Self {
+    pub fn new(display: WlDisplay, wlr_tx: Sender<HashMap<OutputId, MonitorInformation>>) -> Self {
         Self {
             running: true,
             _display: display,
@@ -146,33 +284,116 @@ impl ScreenManagerState {
             current_head: None,
             current_mode: None,
             current_configuration: HashMap::new(),
+            pending_configuration: None,
+            configuration_result: None,
         }
     }
 
     pub fn update_head_configuration(
         &mut self,
-        monitors: Vec<(ObjectId, SwayMonitor)>,
+        configuration: HeadConfiguration,
+        retries: usize,
+        deadline: Instant,
         qh: &QueueHandle<Self>,
     ) {
         if let Some(ref mut output_management) = self.output_manager {
             let output_configuration =
                 output_management.create_configuration(self.update_serial, qh, ());
-            for (id, desired_config) in monitors {
-                if let Some(matching_head) = self.current_configuration.get(&id) {
+            for (id, desired_config) in &configuration.monitors {
+                if let Some((matching_head, monitor_info)) =
+                    self.current_configuration.get(id).and_then(|monitor_info| {
+                        monitor_info.head.as_ref().map(|head| (head, monitor_info))
+                    })
+                {
                     println!("updating monitor: {desired_config:#?}");
                     if desired_config.enabled {
-                        let config = output_configuration.enable_head(&matching_head.head, qh, ());
+                        let config = output_configuration.enable_head(matching_head, qh, ());
+                        let size = (desired_config.width, desired_config.height);
+                        match closest_mode(monitor_info.modes(), size, desired_config.fps)
+                            .and_then(|index| monitor_info.modes()[index].mode().as_ref())
+                        {
+                            Some(mode) => config.set_mode(mode),
+                            None => config.set_custom_mode(
+                                size.0,
+                                size.1,
+                                (desired_config.fps * 1000.).round() as i32,
+                            ),
+                        }
                         config.set_position(desired_config.pos_x, desired_config.pos_y);
                         config.set_scale(desired_config.scale);
-                        config.set_transform(desired_config.rotation.into());
+                        config.set_transform(desired_config.rotation.clone().into());
                     } else {
-                        output_configuration.disable_head(&matching_head.head);
+                        output_configuration.disable_head(matching_head);
                     }
                 }
             }
-            output_configuration.apply();
+            if configuration.test {
+                output_configuration.test();
+            } else {
+                output_configuration.apply();
+            }
+            self.configuration_result = None;
+            self.pending_configuration = Some(PendingConfiguration {
+                configuration,
+                id: output_configuration.id(),
+                retries,
+                deadline,
+            });
         }
     }
+
+    /// report the answer of the compositor to the pending configuration, a cancelled
+    /// configuration is built again with the latest serial
+    ///
+    /// A configuration the compositor does not answer before its deadline is given up, so the
+    /// next one can be sent.
+    pub fn finish_configuration(&mut self, qh: &QueueHandle<Self>) {
+        let Some(pending) = self.pending_configuration.take() else {
+            return;
+        };
+        let result = match self.configuration_result.take() {
+            Some(result) => result,
+            _ if Instant::now() >= pending.deadline => {
+                let err = anyhow::anyhow!("the compositor did not answer the output configuration");
+                eprintln!("{err:#}");
+                let _ = pending.configuration.result_tx.send(Err(err));
+                return;
+            }
+            _ => {
+                self.pending_configuration = Some(pending);
+                return;
+            }
+        };
+        let outcome = match result {
+            zwlr_output_configuration_v1::Event::Succeeded => Ok(()),
+            zwlr_output_configuration_v1::Event::Cancelled
+                if pending.retries < CONFIGURATION_RETRIES =>
+            {
+                eprintln!("output configuration was cancelled, retrying");
+                self.update_head_configuration(
+                    pending.configuration,
+                    pending.retries + 1,
+                    pending.deadline,
+                    qh,
+                );
+                return;
+            }
+            zwlr_output_configuration_v1::Event::Cancelled => Err(anyhow::anyhow!(
+                "the output configuration was cancelled {} times by output changes",
+                pending.retries + 1
+            )),
+            _ if pending.configuration.test => Err(anyhow::anyhow!(
+                "the compositor rejected the output configuration"
+            )),
+            _ => Err(anyhow::anyhow!(
+                "the compositor failed to apply the output configuration"
+            )),
+        };
+        if let Err(err) = &outcome {
+            eprintln!("{err:#}");
+        }
+        let _ = pending.configuration.result_tx.send(outcome);
+    }
 }
 
 impl ScreenManagerState {
@@ -180,7 +401,7 @@ impl ScreenManagerState {
         if self.current_head.is_some() {
             self.finish_head();
         }
-        let mut builder = match self.current_configuration.get(&head.id()) {
+        let mut builder = match self.current_configuration.get(&OutputId::Wlr(head.id())) {
             Some(mi) => MonitorInformationBuilder::from_value(mi),
             None => {
                 // always set dirty if a completly new head (display) is created
@@ -219,7 +440,10 @@ impl ScreenManagerState {
         self.current_head.take().and_then(|hb| {
             hb.build()
                 .and_then(|h| {
-                    self.current_configuration.insert(h.head().id(), h);
+                    if let Some(head) = h.head() {
+                        self.current_configuration
+                            .insert(OutputId::Wlr(head.id()), h);
+                    }
                     Ok(())
                 })
                 .map_err(|err| println!("{err:#?}"))
@@ -371,7 +595,9 @@ impl Dispatch<zwlr_output_head_v1::ZwlrOutputHeadV1, ()> for ScreenManagerState
                 }
             }
             zwlr_output_head_v1::Event::Finished => {
-                app_state.current_configuration.remove(&head.id());
+                app_state
+                    .current_configuration
+                    .remove(&OutputId::Wlr(head.id()));
                 let _ = app_state
                     .wlr_tx
                     .send(app_state.current_configuration.clone());
@@ -405,21 +631,24 @@ impl Dispatch<zwlr_output_configuration_head_v1::ZwlrOutputConfigurationHeadV1,
 
 impl Dispatch<zwlr_output_configuration_v1::ZwlrOutputConfigurationV1, ()> for ScreenManagerState {
     fn event(
-        _state: &mut Self,
-        _proxy: &zwlr_output_configuration_v1::ZwlrOutputConfigurationV1,
+        state: &mut Self,
+        proxy: &zwlr_output_configuration_v1::ZwlrOutputConfigurationV1,
         event: <zwlr_output_configuration_v1::ZwlrOutputConfigurationV1 as Proxy>::Event,
         _data: &(),
         _conn: &Connection,
         _qhandle: &QueueHandle<Self>,
     ) {
-        // TODO should i do something with these events?
-        match event {
-            zwlr_output_configuration_v1::Event::Succeeded => { /*nothing done here yet*/ }
-            zwlr_output_configuration_v1::Event::Failed => { /*nothing done here yet*/ }
-            zwlr_output_configuration_v1::Event::Cancelled => { /*nothing done here yet*/ }
-            _ => {
-                unimplemented!("propbaly an unknown future event has occured and needs a handler!")
-            }
+        // the configuration object is useless once the compositor answered, the answer is
+        // handled in the event loop where the configuration can be sent again
+        let id = proxy.id();
+        proxy.destroy();
+        // late answers to configurations that were given up are dropped
+        if state
+            .pending_configuration
+            .as_ref()
+            .is_some_and(|pending| pending.id == id)
+        {
+            state.configuration_result = Some(event);
         }
     }
 }
@@ -457,11 +686,77 @@ impl Dispatch<zwlr_output_mode_v1::ZwlrOutputModeV1, ()> for ScreenManagerState
     }
 }
 
+/// follow and configure the outputs of a backend other than wlr output management
+///
+/// The outputs are read again whenever `output_events` receives something. Like for wlr output
+/// management, they are only sent when a monitor is connected, disconnected or replaced on its
+/// connector, not when the daemon changed their configuration. `configure` applies the monitors,
+/// or only tests them if the backend `can_test`.
+pub fn output_event_loop(
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+    output_events: &Receiver<()>,
+    can_test: bool,
+    mut read_outputs: impl FnMut() -> anyhow::Result<HashMap<OutputId, MonitorInformation>>,
+    mut configure: impl FnMut(&[SwayMonitor], bool) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut known_outputs = HashSet::new();
+    let mut output_event = true;
+    loop {
+        if output_event {
+            let configuration = read_outputs()?;
+            // a different monitor on the same connector is a hotplug as well
+            let outputs = configuration
+                .iter()
+                .map(|(id, monitor_info)| (id.clone(), monitor_info.identifier()))
+                .collect::<HashSet<(OutputId, String)>>();
+            if outputs != known_outputs {
+                known_outputs = outputs;
+                let _ = wlr_tx.send(configuration);
+            }
+        }
+
+        let mut update_event_happend = false;
+        if let Some(update_head_event) = config_head_rx.try_recv().ok().and_then(|configuration| {
+            if can_test {
+                Some(configuration)
+            } else {
+                configuration.applicable()
+            }
+        }) {
+            update_event_happend = true;
+            let monitors = update_head_event
+                .monitors
+                .into_iter()
+                .map(|(_, monitor)| monitor)
+                .collect::<Vec<SwayMonitor>>();
+            println!("updating monitors: {monitors:#?}");
+            let result = configure(&monitors, update_head_event.test);
+            if let Err(err) = &result {
+                eprintln!("Could not configure outputs: {err:#}");
+            }
+            let _ = update_head_event.result_tx.send(result);
+        }
+        output_event = false;
+        while output_events.try_recv().is_ok() {
+            output_event = true;
+        }
+        // if nothing happend in this loop iteration sleep for a while to save power
+        if !update_event_happend && !output_event {
+            sleep(std::time::Duration::from_millis(100));
+        }
+    }
+}
+
+/// follow and configure the outputs over wlr output management
+///
+/// Fails if the compositor does not offer the output manager, the channels can be handed on to
+/// another backend then.
 pub fn wayland_event_loop(
-    wlr_tx: Sender<HashMap<ObjectId, MonitorInformation>>,
-    config_head_rx: Receiver<Vec<(ObjectId, SwayMonitor)>>,
-) {
-    let conn = Connection::connect_to_env().expect("Error connection to wayland session! Are you sure you are using a wayland based window manager?");
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+) -> anyhow::Result<()> {
+    let conn = Connection::connect_to_env()?;
 
     let display = conn.display();
 
@@ -470,19 +765,59 @@ pub fn wayland_event_loop(
 
     let _registry = display.get_registry(&qh, ());
 
-    let mut state = ScreenManagerState::new(display, wlr_tx);
+    let mut state = ScreenManagerState::new(display, wlr_tx.clone());
+    wl_events.roundtrip(&mut state)?;
+    if state.output_manager.is_none() {
+        bail!("the compositor does not support wlr output management");
+    }
 
     while state.running {
         let x = wl_events.roundtrip(&mut state);
+        state.finish_configuration(&qh);
 
         let mut update_event_happend = false;
-        if let Ok(update_head_event) = config_head_rx.try_recv() {
-            update_event_happend = true;
-            state.update_head_configuration(update_head_event, &qh);
+        // one configuration at a time, a cancelled one is retried before the next is sent
+        if state.pending_configuration.is_none() {
+            if let Ok(update_head_event) = config_head_rx.try_recv() {
+                update_event_happend = true;
+                state.update_head_configuration(
+                    update_head_event,
+                    0,
+                    PendingConfiguration::deadline(),
+                    &qh,
+                );
+            }
         }
         // if nothing happend in this loop iteration sleep for a while to save power
         if !update_event_happend && x.is_ok_and(|num_events| num_events == 0) {
             sleep(std::time::Duration::from_millis(100));
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{closest_mode, MonitorMode, MonitorModeBuilder};
+
+    #[test]
+    fn pick_closest_mode() {
+        let modes = [
+            (3840, 2160, 60000),
+            (2560, 1440, 59951),
+            (2560, 1440, 143912),
+        ]
+        .into_iter()
+        .map(|(width, height, refresh)| {
+            MonitorModeBuilder::default()
+                .size((width, height))
+                .refresh(refresh)
+                .build()
+                .unwrap()
+        })
+        .collect::<Vec<MonitorMode>>();
+        assert_eq!(closest_mode(&modes, (2560, 1440), 60.), Some(1));
+        assert_eq!(closest_mode(&modes, (2560, 1440), 144.), Some(2));
+        assert_eq!(closest_mode(&modes, (1920, 1080), 60.), None);
+    }
 }
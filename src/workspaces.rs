@@ -0,0 +1,519 @@
+use std::{collections::HashMap, fmt::Display};
+
+use itertools::Itertools;
+
+use serde::{de::Error, Deserialize, Serialize};
+use serde_yaml::Value;
+
+/// name of a sway workspace, plain numbers are accepted in the configuration as well
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WorkspaceName(String);
+
+impl WorkspaceName {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// number sway derives from the name, `3:web` is workspace number 3
+    pub fn number(&self) -> Option<i32> {
+        let digits = self
+            .0
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>();
+        digits.parse().ok()
+    }
+
+    /// check if a workspace reported by sway is this workspace
+    ///
+    /// Plain numbers also match named workspaces with that number, `3` matches `3:web`.
+    pub fn matches(&self, sway_name: &str, sway_num: i32) -> bool {
+        self.0 == sway_name
+            || (self.0.chars().all(|c| c.is_ascii_digit()) && self.number() == Some(sway_num))
+    }
+}
+
+impl Display for WorkspaceName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for WorkspaceName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self.0.parse::<u64>() {
+            Ok(num) if num.to_string() == self.0 => serializer.serialize_u64(num),
+            _ => serializer.serialize_str(&self.0),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for WorkspaceName {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value: Value = Deserialize::deserialize(deserializer)?;
+        match value {
+            Value::Number(num) => Ok(WorkspaceName::new(num.to_string())),
+            Value::String(name) if !name.is_empty() => Ok(WorkspaceName::new(name)),
+            _ => Err(Error::custom("expected workspace number or name")),
+        }
+    }
+}
+
+/// workspace assigned to a screen, optionally with outputs to fall back to when the screen is missing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkspaceAssignment {
+    Name(WorkspaceName),
+    WithFallback {
+        name: WorkspaceName,
+        #[serde(default)]
+        fallback: Vec<String>,
+    },
+}
+
+impl WorkspaceAssignment {
+    pub fn name(&self) -> &WorkspaceName {
+        match self {
+            WorkspaceAssignment::Name(name) | WorkspaceAssignment::WithFallback { name, .. } => {
+                name
+            }
+        }
+    }
+
+    pub fn fallback(&self) -> &[String] {
+        match self {
+            WorkspaceAssignment::Name(_) => &[],
+            WorkspaceAssignment::WithFallback { fallback, .. } => fallback,
+        }
+    }
+}
+
+/// numbered workspaces given as `first..last`, both ends included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorkspaceRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+impl WorkspaceRange {
+    pub fn names(&self) -> Vec<WorkspaceName> {
+        (self.first..=self.last)
+            .map(|num| WorkspaceName::new(num.to_string()))
+            .collect()
+    }
+}
+
+impl Serialize for WorkspaceRange {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&format!("{}..{}", self.first, self.last))
+    }
+}
+
+impl<'de> Deserialize<'de> for WorkspaceRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let range = String::deserialize(deserializer)?;
+        let bounds = range.split_once("..").and_then(|(first, last)| {
+            Some((first.trim().parse().ok()?, last.trim().parse().ok()?))
+        });
+        match bounds {
+            Some((first, last)) if first <= last => Ok(WorkspaceRange { first, last }),
+            _ => Err(Error::custom(format!(
+                "expected workspace range like 1..10, got {range}"
+            ))),
+        }
+    }
+}
+
+/// how workspaces of a range are spread over the enabled screens
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DistributionStrategy {
+    /// one workspace per screen in turns
+    #[default]
+    RoundRobin,
+    /// every other screen gets one workspace, the leftmost screen gets all the rest
+    FillPrimary,
+    /// consecutive blocks sized by the width of the screens
+    ByWidth,
+}
+
+/// workspaces distributed automatically over the enabled screens of a profile
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceDistribution {
+    pub range: WorkspaceRange,
+    #[serde(default)]
+    pub strategy: DistributionStrategy,
+}
+
+/// spread workspaces over screens with the given widths, ordered left to right
+///
+/// Returns the workspaces of every screen in the same order as `widths`.
+pub fn distribute(
+    workspaces: &[WorkspaceName],
+    widths: &[i32],
+    strategy: DistributionStrategy,
+) -> Vec<Vec<WorkspaceName>> {
+    let mut distribution = vec![Vec::new(); widths.len()];
+    if widths.is_empty() {
+        return distribution;
+    }
+    let counts = match strategy {
+        DistributionStrategy::RoundRobin => {
+            for (index, ws) in workspaces.iter().enumerate() {
+                distribution[index % widths.len()].push(ws.clone());
+            }
+            return distribution;
+        }
+        DistributionStrategy::FillPrimary => {
+            let others = (widths.len() - 1).min(workspaces.len());
+            let mut counts = vec![0; widths.len()];
+            counts[0] = workspaces.len() - others;
+            for count in counts.iter_mut().skip(1).take(others) {
+                *count = 1;
+            }
+            counts
+        }
+        DistributionStrategy::ByWidth => {
+            // largest remainder method, every screen gets at least one workspace if possible
+            let total_width = widths
+                .iter()
+                .map(|width| *width.max(&1) as f64)
+                .sum::<f64>();
+            let shares = widths
+                .iter()
+                .map(|width| workspaces.len() as f64 * *width.max(&1) as f64 / total_width)
+                .collect::<Vec<f64>>();
+            let mut counts = shares
+                .iter()
+                .map(|share| share.floor() as usize)
+                .collect::<Vec<usize>>();
+            // screens without a workspace first, then the ones with the largest remainder
+            let order = (0..widths.len())
+                .sorted_by(|a, b| {
+                    let remainder = |i: usize| shares[i] - shares[i].floor();
+                    (counts[*a] > 0)
+                        .cmp(&(counts[*b] > 0))
+                        .then(remainder(*b).total_cmp(&remainder(*a)))
+                })
+                .collect::<Vec<usize>>();
+            let remaining = workspaces.len() - counts.iter().sum::<usize>();
+            for index in order.into_iter().take(remaining) {
+                counts[index] += 1;
+            }
+            counts
+        }
+    };
+    let mut workspaces = workspaces.iter();
+    for (screen, count) in distribution.iter_mut().zip(counts) {
+        screen.extend(workspaces.by_ref().take(count).cloned());
+    }
+    distribution
+}
+
+/// what to do with configured workspaces that do not exist in sway when the profile is applied
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub enum MissingWorkspaces {
+    /// leave them alone, the workspace rules place them once they get opened
+    #[default]
+    Skip,
+    /// create them on their output
+    ///
+    /// Sway removes empty workspaces that are not visible, so only workspaces that end up visible stay around.
+    Create,
+}
+
+/// state of a workspace as reported by the compositor
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceState {
+    pub name: String,
+    /// number derived from the name, -1 for workspaces without a number
+    pub num: i32,
+    pub output: String,
+    pub visible: bool,
+    pub focused: bool,
+}
+
+/// single step executed by a workspace backend
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkspaceAction {
+    Move { workspace: String, output: String },
+    Focus(String),
+}
+
+/// workspaces that lived on a monitor, recorded to bring them back when the monitor reconnects
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlacedWorkspaces {
+    pub workspaces: Vec<WorkspaceName>,
+    pub visible: Option<WorkspaceName>,
+}
+
+impl PlacedWorkspaces {
+    /// moves bringing the recorded workspaces to the given output
+    pub fn moves(&self, output: &str) -> Vec<(WorkspaceName, String)> {
+        self.workspaces
+            .iter()
+            .map(|ws| (ws.clone(), output.to_string()))
+            .collect()
+    }
+}
+
+/// record on which monitor every workspace currently lives
+///
+/// `identity` maps an output name to the identifier of the monitor connected to it. Monitors
+/// without any workspace keep their previous record, because sway moves all workspaces away
+/// from a monitor before it is reported as disconnected.
+pub fn record_placement(
+    record: &mut HashMap<String, PlacedWorkspaces>,
+    current: &[WorkspaceState],
+    identity: impl Fn(&str) -> Option<String>,
+) {
+    for (output, output_workspaces) in current
+        .iter()
+        .into_group_map_by(|sway_ws| sway_ws.output.as_str())
+    {
+        if let Some(identifier) = identity(output) {
+            record.insert(
+                identifier,
+                PlacedWorkspaces {
+                    workspaces: output_workspaces
+                        .iter()
+                        .map(|sway_ws| WorkspaceName::new(sway_ws.name.as_str()))
+                        .collect(),
+                    visible: output_workspaces
+                        .iter()
+                        .find(|sway_ws| sway_ws.visible)
+                        .map(|sway_ws| WorkspaceName::new(sway_ws.name.as_str())),
+                },
+            );
+        }
+    }
+}
+
+/// steps moving workspaces to their outputs
+///
+/// Only workspaces that are not on their target output yet are touched. Afterwards the workspace
+/// that was visible on every output is shown again, followed by the existing workspaces in `show`,
+/// and the focused workspace is focused last.
+pub fn plan_moves(
+    current: &[WorkspaceState],
+    moves: &[(WorkspaceName, String)],
+    missing: MissingWorkspaces,
+    show: &[WorkspaceName],
+) -> Vec<WorkspaceAction> {
+    let mut actions = Vec::new();
+    for (ws, output) in moves {
+        // an exact name match wins over a workspace that only shares the number
+        let sway_ws = current
+            .iter()
+            .find(|sway_ws| sway_ws.name == ws.as_str())
+            .or_else(|| {
+                current
+                    .iter()
+                    .find(|sway_ws| ws.matches(&sway_ws.name, sway_ws.num))
+            });
+        let name = match sway_ws {
+            Some(sway_ws) if sway_ws.output == *output => continue,
+            Some(sway_ws) => sway_ws.name.as_str(),
+            None if missing == MissingWorkspaces::Create => ws.as_str(),
+            None => continue,
+        };
+        actions.push(WorkspaceAction::Move {
+            workspace: name.to_string(),
+            output: output.clone(),
+        });
+    }
+    if actions.is_empty() && show.is_empty() {
+        return actions;
+    }
+
+    let visible = current
+        .iter()
+        .filter(|sway_ws| sway_ws.visible && !sway_ws.focused);
+    let shown = current
+        .iter()
+        .filter(|sway_ws| show.iter().any(|ws| ws.as_str() == sway_ws.name));
+    let focused = current.iter().filter(|sway_ws| sway_ws.focused);
+    for sway_ws in visible.chain(shown).chain(focused) {
+        actions.push(WorkspaceAction::Focus(sway_ws.name.clone()));
+    }
+    actions
+}
+
+/// quote an argument of a sway command
+pub fn quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        distribute, plan_moves, record_placement, DistributionStrategy, MissingWorkspaces,
+        PlacedWorkspaces, WorkspaceAction, WorkspaceAssignment, WorkspaceDistribution,
+        WorkspaceName, WorkspaceState,
+    };
+    use itertools::Itertools;
+    use std::collections::HashMap;
+
+    fn sway_ws(name: &str, output: &str, visible: bool, focused: bool) -> WorkspaceState {
+        WorkspaceState {
+            name: name.to_string(),
+            num: WorkspaceName::new(name).number().unwrap_or(-1),
+            output: output.to_string(),
+            visible,
+            focused,
+        }
+    }
+
+    #[test]
+    fn deserialize_workspace_assignments() {
+        let assignments: Vec<WorkspaceAssignment> = serde_yaml::from_str(
+            "[1, mail, \"3:web\", { name: 4, fallback: [eDP-1, Dell XXXXXXA] }]",
+        )
+        .unwrap();
+        assert_eq!(
+            assignments,
+            vec![
+                WorkspaceAssignment::Name(WorkspaceName::new("1")),
+                WorkspaceAssignment::Name(WorkspaceName::new("mail")),
+                WorkspaceAssignment::Name(WorkspaceName::new("3:web")),
+                WorkspaceAssignment::WithFallback {
+                    name: WorkspaceName::new("4"),
+                    fallback: vec!["eDP-1".to_string(), "Dell XXXXXXA".to_string()]
+                },
+            ]
+        );
+        assert_eq!(
+            serde_yaml::to_string(&assignments[..3]).unwrap(),
+            "- 1\n- mail\n- 3:web\n"
+        );
+    }
+
+    #[test]
+    fn match_workspaces() {
+        assert!(WorkspaceName::new("3").matches("3:web", 3));
+        assert!(!WorkspaceName::new("3:web").matches("3", 3));
+        assert!(!WorkspaceName::new("mail").matches("1", 1));
+    }
+
+    #[test]
+    fn move_only_misplaced_workspaces() {
+        let current = [
+            sway_ws("1", "DP-1", true, false),
+            sway_ws("2", "DP-1", false, false),
+            sway_ws("3:web", "eDP-1", true, true),
+        ];
+        let moves = [
+            (WorkspaceName::new("1"), "DP-1".to_string()),
+            (WorkspaceName::new("3"), "DP-1".to_string()),
+            (WorkspaceName::new("mail"), "DP-1".to_string()),
+        ];
+        let move_to = |workspace: &str, output: &str| WorkspaceAction::Move {
+            workspace: workspace.to_string(),
+            output: output.to_string(),
+        };
+        let focus = |workspace: &str| WorkspaceAction::Focus(workspace.to_string());
+        assert_eq!(
+            plan_moves(&current, &moves, MissingWorkspaces::Skip, &[]),
+            vec![move_to("3:web", "DP-1"), focus("1"), focus("3:web")]
+        );
+        assert_eq!(
+            plan_moves(&current, &moves, MissingWorkspaces::Create, &[])[1],
+            move_to("mail", "DP-1")
+        );
+        assert!(plan_moves(&current, &moves[..1], MissingWorkspaces::Create, &[]).is_empty());
+    }
+
+    #[test]
+    fn restore_recorded_placement() {
+        let identity = |output: &str| Some(format!("monitor {output}"));
+        let mut record = HashMap::new();
+        record_placement(
+            &mut record,
+            &[
+                sway_ws("1", "DP-1", false, false),
+                sway_ws("2", "DP-1", true, false),
+                sway_ws("3", "eDP-1", true, true),
+            ],
+            identity,
+        );
+        // DP-1 got unplugged and sway moved its workspaces to eDP-1
+        let evacuated = [
+            sway_ws("1", "eDP-1", false, false),
+            sway_ws("2", "eDP-1", false, false),
+            sway_ws("3", "eDP-1", true, true),
+        ];
+        record_placement(&mut record, &evacuated, identity);
+        let placed = &record["monitor DP-1"];
+        assert_eq!(
+            placed,
+            &PlacedWorkspaces {
+                workspaces: vec![WorkspaceName::new("1"), WorkspaceName::new("2")],
+                visible: Some(WorkspaceName::new("2")),
+            }
+        );
+
+        // DP-1 is back with a fresh workspace
+        let mut reconnected = evacuated.to_vec();
+        reconnected.push(sway_ws("4", "DP-1", true, false));
+        let actions = plan_moves(
+            &reconnected,
+            &placed.moves("DP-1"),
+            MissingWorkspaces::Skip,
+            placed.visible.as_slice(),
+        );
+        assert_eq!(
+            actions[2..],
+            [
+                WorkspaceAction::Focus("4".to_string()),
+                WorkspaceAction::Focus("2".to_string()),
+                WorkspaceAction::Focus("3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn distribute_workspace_range() {
+        let distribution: WorkspaceDistribution =
+            serde_yaml::from_str("{ range: 1..7, strategy: by-width }").unwrap();
+        assert_eq!(distribution.strategy, DistributionStrategy::ByWidth);
+        assert!(serde_yaml::from_str::<WorkspaceDistribution>("range: 5..1").is_err());
+        let names = distribution.range.names();
+        let numbers = |strategy| {
+            distribute(&names, &[3840, 1920, 1920], strategy)
+                .into_iter()
+                .map(|screen| screen.iter().map(|ws| ws.to_string()).join(" "))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            numbers(DistributionStrategy::RoundRobin),
+            ["1 4 7", "2 5", "3 6"]
+        );
+        assert_eq!(
+            numbers(DistributionStrategy::FillPrimary),
+            ["1 2 3 4 5", "6", "7"]
+        );
+        assert_eq!(
+            numbers(DistributionStrategy::ByWidth),
+            ["1 2 3", "4 5", "6 7"]
+        );
+    }
+}
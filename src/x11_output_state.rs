@@ -0,0 +1,505 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use wayland_client::protocol::wl_output::Transform;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::{self, ConnectionExt as _, ModeFlag, ModeInfo, NotifyMask, Rotation},
+        render,
+        xproto::{Atom, AtomEnum, ConnectionExt as _, Window},
+        Event,
+    },
+    CURRENT_TIME,
+};
+
+use crate::{
+    configuration::SwayMonitor,
+    wlr_output_state::{
+        output_event_loop, pnp_vendor, read_pnp_ids, HeadConfiguration, MonitorInformation,
+        MonitorInformationBuilder, MonitorModeBuilder, OutputId,
+    },
+};
+
+/// make, model and serial of a monitor the way wlroots reports them
+#[derive(Debug, PartialEq)]
+struct EdidInfo {
+    make: String,
+    model: String,
+    serial: String,
+}
+
+/// text of an edid display descriptor block with the given tag
+fn edid_descriptor(edid: &[u8], tag: u8) -> Option<String> {
+    edid.get(54..126)?
+        .chunks(18)
+        .find(|block| block[0..3] == [0, 0, 0] && block[3] == tag)
+        .map(|block| {
+            String::from_utf8_lossy(&block[5..])
+                .split('\n')
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .to_string()
+        })
+}
+
+fn parse_edid(edid: &[u8], pnp_ids: &str) -> Option<EdidInfo> {
+    if edid.len() < 128 || edid[0..8] != [0, 255, 255, 255, 255, 255, 255, 0] {
+        return None;
+    }
+    let manufacturer = u16::from_be_bytes([edid[8], edid[9]]);
+    let pnp_id = [10, 5, 0]
+        .iter()
+        .map(|shift| (((manufacturer >> shift) & 0x1f) as u8 + b'A' - 1) as char)
+        .collect::<String>();
+    let make = pnp_vendor(&pnp_id, pnp_ids);
+    let product = u16::from_le_bytes([edid[10], edid[11]]);
+    let serial_number = u32::from_le_bytes([edid[12], edid[13], edid[14], edid[15]]);
+    Some(EdidInfo {
+        make,
+        model: edid_descriptor(edid, 0xfc).unwrap_or(format!("0x{product:04X}")),
+        serial: edid_descriptor(edid, 0xff).unwrap_or(if serial_number != 0 {
+            format!("0x{serial_number:08X}")
+        } else {
+            String::new()
+        }),
+    })
+}
+
+/// refresh rate of a mode in mHz
+fn refresh(mode: &ModeInfo) -> f64 {
+    let flags = u32::from(mode.mode_flags);
+    let mut vtotal = mode.vtotal as f64;
+    if flags & u32::from(ModeFlag::DOUBLE_SCAN) != 0 {
+        vtotal *= 2.;
+    }
+    if flags & u32::from(ModeFlag::INTERLACE) != 0 {
+        vtotal /= 2.;
+    }
+    if mode.htotal == 0 || vtotal == 0. {
+        return 0.;
+    }
+    mode.dot_clock as f64 * 1000. / (mode.htotal as f64 * vtotal)
+}
+
+fn transform(rotation: Rotation) -> Transform {
+    if rotation.contains(Rotation::ROTATE90) {
+        Transform::_90
+    } else if rotation.contains(Rotation::ROTATE180) {
+        Transform::_180
+    } else if rotation.contains(Rotation::ROTATE270) {
+        Transform::_270
+    } else {
+        Transform::Normal
+    }
+}
+
+fn rotation(transform: Transform) -> Rotation {
+    match transform {
+        Transform::_90 => Rotation::ROTATE90,
+        Transform::_180 => Rotation::ROTATE180,
+        Transform::_270 => Rotation::ROTATE270,
+        _ => Rotation::ROTATE0,
+    }
+}
+
+fn fixed(value: f64) -> render::Fixed {
+    (value * 65536.).round() as render::Fixed
+}
+
+/// crtc transform scaling the screen area of an output down by `scale`, like `xrandr --scale`
+fn scale_transform(scale: f64) -> render::Transform {
+    render::Transform {
+        matrix11: fixed(1. / scale),
+        matrix12: 0,
+        matrix13: 0,
+        matrix21: 0,
+        matrix22: fixed(1. / scale),
+        matrix23: 0,
+        matrix31: 0,
+        matrix32: 0,
+        matrix33: fixed(1.),
+    }
+}
+
+/// outputs of the x screen managed over randr
+struct RandrScreen<C: Connection> {
+    conn: C,
+    root: Window,
+    edid: Atom,
+    pnp_ids: String,
+}
+
+impl<C: Connection> RandrScreen<C> {
+    fn output_name(info: &randr::GetOutputInfoReply) -> String {
+        String::from_utf8_lossy(&info.name).to_string()
+    }
+
+    /// connected outputs keyed by their name
+    fn current_configuration(&self) -> anyhow::Result<HashMap<OutputId, MonitorInformation>> {
+        let resources = self
+            .conn
+            .randr_get_screen_resources_current(self.root)?
+            .reply()?;
+        let mut configuration = HashMap::new();
+        for &output in &resources.outputs {
+            let info = self
+                .conn
+                .randr_get_output_info(output, resources.config_timestamp)?
+                .reply()?;
+            if info.connection != randr::Connection::CONNECTED {
+                continue;
+            }
+            let name = Self::output_name(&info);
+            let edid = self
+                .conn
+                .randr_get_output_property(output, self.edid, AtomEnum::ANY, 0, 128, false, false)?
+                .reply()?;
+            let edid_info = parse_edid(&edid.data, &self.pnp_ids);
+            let mut builder = MonitorInformationBuilder::default();
+            builder
+                .name(name.clone())
+                .size((info.mm_width as i32, info.mm_height as i32))
+                .enabled((info.crtc != 0) as i32)
+                .transform(Transform::Normal)
+                .scale(1.);
+            if let Some(edid_info) = edid_info {
+                builder
+                    .description(format!(
+                        "{} {} {} ({name})",
+                        edid_info.make, edid_info.model, edid_info.serial
+                    ))
+                    .make(edid_info.make)
+                    .model(edid_info.model)
+                    .serial(Some(edid_info.serial));
+            }
+            let mut current_mode = 0;
+            if info.crtc != 0 {
+                let crtc = self
+                    .conn
+                    .randr_get_crtc_info(info.crtc, resources.config_timestamp)?
+                    .reply()?;
+                let crtc_transform = self.conn.randr_get_crtc_transform(info.crtc)?.reply()?;
+                current_mode = crtc.mode;
+                builder
+                    .position((crtc.x as i32, crtc.y as i32))
+                    .transform(transform(crtc.rotation));
+                if crtc_transform.current_transform.matrix11 != 0 {
+                    builder.scale(65536. / crtc_transform.current_transform.matrix11 as f64);
+                }
+            }
+            for (index, mode) in info.modes.iter().enumerate() {
+                if let Some(mode_info) = resources
+                    .modes
+                    .iter()
+                    .find(|mode_info| mode_info.id == *mode)
+                {
+                    builder.add_mode(
+                        MonitorModeBuilder::default()
+                            .size((mode_info.width as i32, mode_info.height as i32))
+                            .refresh(refresh(mode_info))
+                            .preferred(index < info.num_preferred as usize)
+                            .current(*mode == current_mode)
+                            .build()?,
+                    );
+                }
+            }
+            configuration.insert(OutputId::Name(name), builder.build()?);
+        }
+        Ok(configuration)
+    }
+
+    /// configure the crtcs of the outputs and resize the screen to the layout
+    fn apply(&self, monitors: &[SwayMonitor]) -> anyhow::Result<()> {
+        let resources = self
+            .conn
+            .randr_get_screen_resources_current(self.root)?
+            .reply()?;
+        let mut outputs = HashMap::new();
+        for &output in &resources.outputs {
+            let info = self
+                .conn
+                .randr_get_output_info(output, resources.config_timestamp)?
+                .reply()?;
+            outputs.insert(Self::output_name(&info), (output, info));
+        }
+        let (width, height) = monitors
+            .iter()
+            .filter(|monitor| monitor.enabled)
+            .map(|monitor| monitor.rect())
+            .fold((1, 1), |(width, height), rect| {
+                (
+                    width.max(rect.x + rect.width),
+                    height.max(rect.y + rect.height),
+                )
+            });
+
+        // crtcs that are disabled or do not fit into the new screen size are turned off first
+        let mut free_crtcs = Vec::new();
+        for &crtc in &resources.crtcs {
+            let crtc_info = self
+                .conn
+                .randr_get_crtc_info(crtc, resources.config_timestamp)?
+                .reply()?;
+            let disabled = crtc_info.outputs.iter().any(|output| {
+                monitors.iter().any(|monitor| {
+                    !monitor.enabled
+                        && outputs
+                            .get(&monitor.name)
+                            .is_some_and(|(monitor_output, _)| monitor_output == output)
+                })
+            });
+            let too_big = crtc_info.x as i32 + crtc_info.width as i32 > width
+                || crtc_info.y as i32 + crtc_info.height as i32 > height;
+            if crtc_info.mode != 0 && (disabled || too_big) {
+                self.conn
+                    .randr_set_crtc_config(
+                        crtc,
+                        CURRENT_TIME,
+                        resources.config_timestamp,
+                        0,
+                        0,
+                        0,
+                        Rotation::ROTATE0,
+                        &[],
+                    )?
+                    .reply()?;
+            }
+            if crtc_info.outputs.is_empty() || disabled {
+                free_crtcs.push(crtc);
+            }
+        }
+
+        // keep the dpi at 96
+        self.conn
+            .randr_set_screen_size(
+                self.root,
+                width as u16,
+                height as u16,
+                (width as f64 * 25.4 / 96.) as u32,
+                (height as f64 * 25.4 / 96.) as u32,
+            )?
+            .check()?;
+        for monitor in monitors.iter().filter(|monitor| monitor.enabled) {
+            let Some((output, info)) = outputs.get(&monitor.name) else {
+                continue;
+            };
+            let crtc = if info.crtc != 0 {
+                info.crtc
+            } else {
+                match free_crtcs.iter().position(|crtc| info.crtcs.contains(crtc)) {
+                    Some(index) => free_crtcs.remove(index),
+                    None => {
+                        eprintln!("No free crtc for {}", monitor.name);
+                        continue;
+                    }
+                }
+            };
+            let Some(mode) = resources
+                .modes
+                .iter()
+                .filter(|mode| info.modes.contains(&mode.id))
+                .filter(|mode| {
+                    (mode.width as i32, mode.height as i32) == (monitor.width, monitor.height)
+                })
+                .min_by(|a, b| {
+                    (refresh(a) / 1000. - monitor.fps)
+                        .abs()
+                        .total_cmp(&(refresh(b) / 1000. - monitor.fps).abs())
+                })
+            else {
+                eprintln!(
+                    "{} has no mode {}x{}",
+                    monitor.name, monitor.width, monitor.height
+                );
+                continue;
+            };
+            let filter: &[u8] = if monitor.scale == 1. {
+                b"nearest"
+            } else {
+                b"bilinear"
+            };
+            self.conn.randr_set_crtc_transform(
+                crtc,
+                scale_transform(monitor.scale),
+                filter,
+                &[],
+            )?;
+            self.conn
+                .randr_set_crtc_config(
+                    crtc,
+                    CURRENT_TIME,
+                    resources.config_timestamp,
+                    monitor.pos_x as i16,
+                    monitor.pos_y as i16,
+                    mode.id,
+                    rotation(monitor.rotation.clone().into()),
+                    &[*output],
+                )?
+                .reply()?;
+        }
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+/// follow and configure the outputs of an xorg session over randr
+pub fn x11_event_loop(
+    wlr_tx: &Sender<HashMap<OutputId, MonitorInformation>>,
+    config_head_rx: &Receiver<HeadConfiguration>,
+) -> anyhow::Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let edid = conn.intern_atom(false, b"EDID")?.reply()?.atom;
+    let screen = RandrScreen {
+        conn,
+        root,
+        edid,
+        pnp_ids: read_pnp_ids(),
+    };
+
+    // a second connection waits for the randr events
+    let (events, screen_num) = x11rb::connect(None)?;
+    events.randr_select_input(
+        events.setup().roots[screen_num].root,
+        NotifyMask::SCREEN_CHANGE | NotifyMask::OUTPUT_CHANGE,
+    )?;
+    events.flush()?;
+    let (event_tx, event_rx) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(event) = events.wait_for_event() {
+            if matches!(
+                event,
+                Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_)
+            ) && event_tx.send(()).is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    output_event_loop(
+        wlr_tx,
+        config_head_rx,
+        &event_rx,
+        false,
+        || screen.current_configuration(),
+        |monitors, _| screen.apply(monitors),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_edid, EdidInfo, RandrScreen};
+    use crate::configuration::{ScreenRotation, SwayMonitor};
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+    use x11rb::{connection::Connection, protocol::xproto::ConnectionExt};
+
+    #[test]
+    fn parse_edid_make_model_serial() {
+        let mut edid = vec![0u8; 128];
+        edid[0..8].copy_from_slice(&[0, 255, 255, 255, 255, 255, 255, 0]);
+        // "DEL" packed into 5 bit letters
+        edid[8..10].copy_from_slice(&0x10acu16.to_be_bytes());
+        edid[10..12].copy_from_slice(&0xa0e3u16.to_le_bytes());
+        edid[12..16].copy_from_slice(&0x4c4c4c4cu32.to_le_bytes());
+        edid[54..59].copy_from_slice(&[0, 0, 0, 0xfc, 0]);
+        edid[59..72].copy_from_slice(b"DELL U2720Q\n ");
+        edid[72..77].copy_from_slice(&[0, 0, 0, 0xff, 0]);
+        edid[77..90].copy_from_slice(b"XXXXXXA\n     ");
+        assert_eq!(
+            parse_edid(
+                &edid,
+                "DEC\tDigital Equipment Corporation\nDEL\tDell Inc.\n"
+            ),
+            Some(EdidInfo {
+                make: "Dell Inc.".to_string(),
+                model: "DELL U2720Q".to_string(),
+                serial: "XXXXXXA".to_string(),
+            })
+        );
+
+        // without descriptors the codes are used
+        edid[54..90].fill(1);
+        assert_eq!(
+            parse_edid(&edid, ""),
+            Some(EdidInfo {
+                make: "DEL".to_string(),
+                model: "0xA0E3".to_string(),
+                serial: "0x4C4C4C4C".to_string(),
+            })
+        );
+        assert_eq!(parse_edid(&edid[..64], ""), None);
+    }
+
+    /// needs Xvfb, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn randr_screen_on_xvfb() {
+        let mut xvfb = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-screen", "0", "1920x1080x24"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Xvfb is not installed");
+        let mut display = String::new();
+        BufReader::new(xvfb.stdout.take().unwrap())
+            .read_line(&mut display)
+            .unwrap();
+        let (conn, screen_num) = x11rb::connect(Some(&format!(":{}", display.trim()))).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+        let edid = conn
+            .intern_atom(false, b"EDID")
+            .unwrap()
+            .reply()
+            .unwrap()
+            .atom;
+        let screen = RandrScreen {
+            conn,
+            root,
+            edid,
+            pnp_ids: String::new(),
+        };
+
+        let configuration = screen.current_configuration().unwrap();
+        assert_eq!(configuration.len(), 1);
+        let monitor_info = configuration.values().next().unwrap();
+        assert_eq!(*monitor_info.enabled(), 1);
+        assert_eq!(*monitor_info.position(), (0, 0));
+        assert_eq!(*monitor_info.active_mode().size(), (1920, 1080));
+
+        let mut monitor = SwayMonitor {
+            identifier: monitor_info.identifier(),
+            mirror: None,
+            enabled: false,
+            name: monitor_info.name().to_string(),
+            width: 1920,
+            height: 1080,
+            fps: *monitor_info.active_mode().refresh() / 1000.,
+            pos_x: 0,
+            pos_y: 0,
+            scale: 1.,
+            rotation: ScreenRotation::Landscape,
+            workspaces: Vec::new(),
+        };
+        screen.apply(std::slice::from_ref(&monitor)).unwrap();
+        let configuration = screen.current_configuration().unwrap();
+        assert_eq!(*configuration.values().next().unwrap().enabled(), 0);
+
+        monitor.enabled = true;
+        screen.apply(&[monitor]).unwrap();
+        let configuration = screen.current_configuration().unwrap();
+        let monitor_info = configuration.values().next().unwrap();
+        assert_eq!(*monitor_info.enabled(), 1);
+        assert_eq!(*monitor_info.active_mode().size(), (1920, 1080));
+
+        let _ = xvfb.kill();
+        let _ = xvfb.wait();
+    }
+}